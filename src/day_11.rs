@@ -4,7 +4,9 @@ use anyhow::Error;
 use ndarray::Array2;
 use num::{FromPrimitive, ToPrimitive};
 
-use crate::intcode_compiler::IntCode;
+use crate::intcode_compiler::device::{InputSource, OutputSink};
+use crate::intcode_compiler::loader::parse_program;
+use crate::intcode_compiler::{IntCode, RunState};
 use crate::utility::plot;
 
 type Point = (i64, i64);
@@ -64,55 +66,31 @@ impl Direction {
 }
 
 struct PaintingRobot {
-    program: IntCode,
     grid: HashMap<Point, Color>,
     direction: Direction,
     location: Point,
+    /// The next output is a turn, the current square having been painted
+    painted: bool,
 }
 
 impl PaintingRobot {
-    fn new(input: &str, start_color: Color) -> Result<Self, Error> {
+    fn new(start_color: Color) -> Self {
         let mut grid = HashMap::new();
         grid.insert((0, 0), start_color);
-        Ok(PaintingRobot {
-            program: IntCode::new(
-                input
-                    .split(',')
-                    .map(|i| i.parse::<i64>())
-                    .collect::<Result<Vec<_>, _>>()?,
-                0,
-            ),
+        PaintingRobot {
             grid,
             direction: Direction::Up,
             location: (0, 0),
-        })
-    }
-
-    fn run(&mut self) -> Result<(), Error> {
-        while !self.program.halted {
-            self.paint()?;
+            painted: false,
         }
-        Ok(())
     }
 
-    fn paint(&mut self) -> Result<(), Error> {
-        self.program.input_value = self.get_color_at(&self.location).to_i64().unwrap();
-        loop {
-            let (opcode, mut parameters) = self.program.make_instruction()?;
-            if self.program.process_instruction(opcode, &mut parameters)? {
-                break;
-            }
-            if self.program.outputs.len() == 2 {
-                let color = FromPrimitive::from_i64(self.program.outputs[0]).unwrap();
-                self.set_color_at(self.location, color);
-                let turn = FromPrimitive::from_i64(self.program.outputs[1]).unwrap();
-                self.direction = self.direction.turn(turn);
-                self.direction.step(&mut self.location);
-                self.program.outputs = Vec::new();
-                break;
-            }
+    fn run(&mut self, input: &str) -> Result<(), Error> {
+        let mut program = IntCode::new(parse_program(input)?);
+        match program.run_device(self)? {
+            RunState::Halted => Ok(()),
+            state => Err(anyhow!("robot stopped: {:?}", state)),
         }
-        Ok(())
     }
 
     fn get_color_at(&self, location: &Point) -> Color {
@@ -124,15 +102,40 @@ impl PaintingRobot {
     }
 }
 
+/// The camera reports the color under the robot
+impl InputSource for PaintingRobot {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        Ok(self.get_color_at(&self.location).to_i64())
+    }
+}
+
+/// Outputs alternate between the color to paint and the direction to turn
+impl OutputSink for PaintingRobot {
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        if self.painted {
+            let turn =
+                FromPrimitive::from_i64(value).ok_or_else(|| anyhow!("bad turn {}", value))?;
+            self.direction = self.direction.turn(turn);
+            self.direction.step(&mut self.location);
+        } else {
+            let color =
+                FromPrimitive::from_i64(value).ok_or_else(|| anyhow!("bad color {}", value))?;
+            self.set_color_at(self.location, color);
+        }
+        self.painted = !self.painted;
+        Ok(())
+    }
+}
+
 pub fn solve_day_11_1(input: &str) -> Result<usize, Error> {
-    let mut robot = PaintingRobot::new(input, Color::Black)?;
-    robot.run()?;
+    let mut robot = PaintingRobot::new(Color::Black);
+    robot.run(input)?;
     Ok(robot.grid.len())
 }
 
 pub fn solve_day_11_2(input: &str) -> Result<String, Error> {
-    let mut robot = PaintingRobot::new(input, Color::White)?;
-    robot.run()?;
+    let mut robot = PaintingRobot::new(Color::White);
+    robot.run(input)?;
     let (mut min_x, mut min_y, mut max_x, mut max_y) = (
         ::std::i64::MAX,
         ::std::i64::MAX,
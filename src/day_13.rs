@@ -5,8 +5,9 @@ use ndarray::Array2;
 use num::{FromPrimitive, ToPrimitive};
 use text_io::read;
 
-use crate::intcode_compiler::{IntCode, OpCode};
-use crate::utility::{input_from_file, write_to_file};
+use crate::intcode_compiler::device::{InputSource, OutputSink, Recorded};
+use crate::intcode_compiler::loader::parse_program;
+use crate::intcode_compiler::{IntCode, RunState};
 
 // 0 is an empty tile. No game object appears in this tile.
 // 1 is a wall tile. Walls are indestructible barriers.
@@ -76,80 +77,62 @@ impl Default for Joystick {
     }
 }
 
+/// Joystick moves typed as a (left), s (neutral) or d (right).
+/// Anything else leaves the joystick where it was.
+#[derive(Default)]
+struct Keyboard {
+    joystick: Joystick,
+}
+
+impl InputSource for Keyboard {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        println!("Move joystick:");
+        let i: String = read!("{}\n");
+        let i = match i.chars().next() {
+            Some('A') | Some('a') => -1,
+            Some('D') | Some('d') => 1,
+            Some('S') | Some('s') => 0,
+            _ => 2,
+        };
+        if let Some(joystick) = FromPrimitive::from_i64(i) {
+            self.joystick = joystick;
+        }
+        Ok(self.joystick.to_i64())
+    }
+}
+
 struct ArcadeCabinet {
-    program: IntCode,
     tiles: Vec<Tile>,
     joystick: Joystick,
-    joysticks: Vec<Joystick>,
-    input_index: usize,
+    controller: Recorded<Keyboard>,
+    /// Output not yet making up a whole tile
+    partial: Vec<i64>,
     score: i64,
 }
 
 impl ArcadeCabinet {
-    fn new(input: Vec<i64>) -> Result<Self, Error> {
-        Ok(ArcadeCabinet {
-            program: IntCode::new(input, 0),
+    fn new(controller: Recorded<Keyboard>) -> Self {
+        ArcadeCabinet {
             tiles: Vec::new(),
             score: 0,
             joystick: Joystick::default(),
-            input_index: 0,
-            joysticks: Vec::new(),
-        })
-    }
-
-    fn run(&mut self) -> Result<(), Error> {
-        while !self.program.halted {
-            self.step()?;
+            controller,
+            partial: Vec::with_capacity(3),
         }
-        Ok(())
     }
 
-    fn read_input(&mut self) {
-        if self.input_index < self.joysticks.len() {
-            self.joystick = self.joysticks[self.input_index];
-            self.program.input_value = self.joysticks[self.input_index].to_i64().unwrap_or(0);
-        } else {
-            println!("Move joystick:");
-            let i: String = read!("{}\n");
-            let i = match i.chars().next() {
-                Some('A') | Some('a') => -1,
-                Some('D') | Some('d') => 1,
-                Some('S') | Some('s') => 0,
-                _ => 2,
-            };
-            if let Some(joystick) = FromPrimitive::from_i64(i) {
-                self.joystick = joystick;
-                self.program.input_value = i;
-            }
-            self.joysticks.push(self.joystick)
+    fn run(&mut self, program: Vec<i64>) -> Result<(), Error> {
+        match IntCode::new(program).run_device(self)? {
+            RunState::Halted => Ok(()),
+            state => Err(anyhow!("arcade stopped: {:?}", state)),
         }
-        self.input_index += 1;
     }
 
-    fn step(&mut self) -> Result<(), Error> {
-        loop {
-            let (opcode, mut parameters) = self.program.make_instruction()?;
-            if opcode == OpCode::Input {
-                self.read_input();
-            }
-            if self.program.process_instruction(opcode, &mut parameters)? {
-                break;
-            }
-            if self.program.outputs.len() == 3 {
-                if self.program.outputs[0] == -1 && self.program.outputs[1] == 0 {
-                    self.score = self.program.outputs[2];
-                } else {
-                    self.tiles.push(Tile::new(&self.program.outputs));
-                }
-                self.program.outputs = Vec::new();
-                println!("SCORE: {}", self.score);
-                println!("Joystick: {:?}", self.joystick);
-                println!("{}", TileID::plot(&self.make_grid()));
-                println!();
-                break;
-            }
-        }
-        Ok(())
+    fn draw(&self) {
+        println!("SCORE: {}", self.score);
+        println!("Joystick: {:?}", self.joystick);
+        println!("{}", TileID::plot(&self.make_grid()));
+        println!();
     }
 
     fn make_grid(&self) -> Array2<u32> {
@@ -185,14 +168,38 @@ impl ArcadeCabinet {
     }
 }
 
+/// The joystick
+impl InputSource for ArcadeCabinet {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        let input = self.controller.next_input()?;
+        if let Some(i) = input {
+            self.joystick = FromPrimitive::from_i64(i).unwrap_or_default();
+        }
+        Ok(input)
+    }
+}
+
+/// The screen and score display, updated by (x, y, tile) triples
+impl OutputSink for ArcadeCabinet {
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        self.partial.push(value);
+        if self.partial.len() < 3 {
+            return Ok(());
+        }
+        if self.partial[0] == -1 && self.partial[1] == 0 {
+            self.score = self.partial[2];
+        } else {
+            self.tiles.push(Tile::new(&self.partial));
+        }
+        self.partial.clear();
+        self.draw();
+        Ok(())
+    }
+}
+
 pub fn solve_day_13_1(input: &str) -> Result<usize, Error> {
-    let mut arcade = ArcadeCabinet::new(
-        input
-            .split(',')
-            .map(|i| i.parse::<i64>())
-            .collect::<Result<Vec<_>, _>>()?,
-    )?;
-    arcade.run()?;
+    let mut arcade = ArcadeCabinet::new(Recorded::new(Keyboard::default()));
+    arcade.run(parse_program(input)?)?;
     Ok(arcade
         .tiles
         .iter()
@@ -200,38 +207,22 @@ pub fn solve_day_13_1(input: &str) -> Result<usize, Error> {
         .count())
 }
 
-fn play(arcade: &mut ArcadeCabinet, read: bool, write: bool) -> Result<i64, Error> {
-    if read {
-        let joysticks = input_from_file("data/day_13.output")?
-            .split(',')
-            .map(|i| i.parse::<i64>())
-            .collect::<Result<Vec<_>, _>>()?;
-        arcade.joysticks = joysticks
-            .into_iter()
-            .map(|i| FromPrimitive::from_i64(i).unwrap_or_default())
-            .collect();
-    }
-    arcade.run()?;
+fn play(program: Vec<i64>, read: bool, write: bool) -> Result<i64, Error> {
+    let controller = if read {
+        Recorded::load("data/day_13.output", Keyboard::default())?
+    } else {
+        Recorded::new(Keyboard::default())
+    };
+    let mut arcade = ArcadeCabinet::new(controller);
+    arcade.run(program)?;
     if write {
-        write_to_file(
-            "data/day_13.output",
-            &arcade
-                .joysticks
-                .iter()
-                .map(|j| j.to_i64().unwrap().to_string())
-                .collect::<Vec<_>>()
-                .join(","),
-        )?;
+        arcade.controller.save("data/day_13.output")?;
     }
     Ok(arcade.score)
 }
 
 pub fn solve_day_13_2(input: &str) -> Result<i64, Error> {
-    let mut program = input
-        .split(',')
-        .map(|i| i.parse::<i64>())
-        .collect::<Result<Vec<_>, _>>()?;
+    let mut program = parse_program(input)?;
     program[0] = 2;
-    let mut arcade = ArcadeCabinet::new(program)?;
-    Ok(play(&mut arcade, true, true)?)
+    play(program, true, true)
 }
@@ -1,5 +1,11 @@
+use std::convert::TryFrom;
+
 use anyhow::Error;
 
+use crate::intcode_compiler::fuzz::Outcome;
+use crate::intcode_compiler::loader::parse_program;
+use crate::intcode_compiler::symbolic::SymbolicMachine;
+
 fn process_intcode(intcode: &mut [usize]) {
     for i in (0..intcode.len()).step_by(4) {
         match intcode[i] {
@@ -11,7 +17,37 @@ fn process_intcode(intcode: &mut [usize]) {
     }
 }
 
+/// `process_intcode` on an IntCode style program, for fuzzing the two against each other
+fn run_process_intcode(program: &[i64]) -> Outcome {
+    let mut intcode: Vec<_> = program.iter().map(|&i| i as usize).collect();
+    process_intcode(&mut intcode);
+    Ok(intcode.into_iter().map(|i| i as i64).collect())
+}
+
+/// Solve for noun and verb symbolically, checking the answer with a real run
 fn find_noun_verb(intcode: &[usize], output_equals: usize) -> Option<(usize, usize)> {
+    let program: Vec<_> = intcode.iter().map(|&i| i as i64).collect();
+    let mut machine = SymbolicMachine::new(&program);
+    let noun = machine.unknown_cell(1);
+    let verb = machine.unknown_cell(2);
+    let solution = match machine.run().ok().and_then(|_| machine.cell(0)) {
+        Some(output) => output.solve(output_equals as i64, &[0..100, 0..100]),
+        None => return search_noun_verb(intcode, output_equals),
+    }?;
+    let (noun, verb) = (solution[noun] as usize, solution[verb] as usize);
+    let mut intcode_noun_verb = intcode.to_vec();
+    intcode_noun_verb[1] = noun;
+    intcode_noun_verb[2] = verb;
+    process_intcode(&mut intcode_noun_verb);
+    if intcode_noun_verb[0] == output_equals {
+        Some((noun, verb))
+    } else {
+        search_noun_verb(intcode, output_equals)
+    }
+}
+
+/// Try every noun and verb
+fn search_noun_verb(intcode: &[usize], output_equals: usize) -> Option<(usize, usize)> {
     for noun in 0..100 {
         for verb in 0..100 {
             let mut intcode_noun_verb = intcode.to_vec();
@@ -26,11 +62,16 @@ fn find_noun_verb(intcode: &[usize], output_equals: usize) -> Option<(usize, usi
     None
 }
 
+/// Day 2 programs hold only addresses and non-negative values
+fn parse_day_2(input: &str) -> Result<Vec<usize>, Error> {
+    parse_program(input)?
+        .into_iter()
+        .map(|i| Ok(usize::try_from(i)?))
+        .collect()
+}
+
 pub fn solve_day_2_1(input: &str) -> Result<usize, Error> {
-    let mut intcode: Vec<_> = input
-        .split(',')
-        .map(|i| i.parse::<usize>())
-        .collect::<Result<_, _>>()?;
+    let mut intcode = parse_day_2(input)?;
     intcode[1] = 12;
     intcode[2] = 2;
     process_intcode(&mut intcode);
@@ -38,10 +79,7 @@ pub fn solve_day_2_1(input: &str) -> Result<usize, Error> {
 }
 
 pub fn solve_day_2_2(input: &str) -> Result<usize, Error> {
-    let intcode: Vec<_> = input
-        .split(',')
-        .map(|i| i.parse::<usize>())
-        .collect::<Result<_, _>>()?;
+    let intcode = parse_day_2(input)?;
     match find_noun_verb(&intcode, 19_690_720) {
         Some((noun, verb)) => Ok(100 * noun + verb),
         None => Err(anyhow!("(noun verb) pair not found")),
@@ -50,6 +88,7 @@ pub fn solve_day_2_2(input: &str) -> Result<usize, Error> {
 
 #[cfg(test)]
 mod tests {
+    use crate::intcode_compiler::fuzz::differential;
     use crate::utility::input_from_file;
 
     use super::*;
@@ -73,12 +112,18 @@ mod tests {
 
     #[test]
     fn test_2() -> Result<(), Error> {
-        let intcode: Vec<_> = input_from_file("data/day_2.input")?
-            .split(',')
-            .map(|i| i.parse::<usize>())
-            .collect::<Result<_, _>>()?;
+        let intcode = parse_day_2(&input_from_file("data/day_2.input")?)?;
         let (noun, verb) = find_noun_verb(&intcode, 4945026).unwrap();
         assert_eq!(1202, 100 * noun + verb);
         Ok(())
     }
+
+    #[test]
+    fn test_3() {
+        // the add makes an opcode 7, which only IntCode understands
+        assert_eq!(
+            differential(1, 1000, run_process_intcode),
+            Some(vec![1, 2, 3, 4, 1, 0, 0, 0, 99])
+        );
+    }
 }
@@ -1,32 +1,172 @@
-use anyhow::Error;
+use std::collections::VecDeque;
+use std::fmt;
+
 use num::FromPrimitive;
 
-pub struct IntCode {
-    pub program: Vec<i64>,
+use memory::Memory;
+use watch::{WatchHit, WatchKind};
+
+pub mod ascii;
+pub mod assembler;
+#[cfg(feature = "bigint")]
+pub mod bigint;
+pub mod control_flow;
+pub mod debugger;
+pub mod device;
+pub mod disassembler;
+pub mod fuzz;
+pub mod guard;
+pub mod history;
+pub mod loader;
+pub mod memory;
+pub mod network;
+pub mod profile;
+pub mod snapshot;
+pub mod symbolic;
+pub mod threaded;
+pub mod topology;
+pub mod trace;
+pub mod transpiler;
+pub mod watch;
+
+#[derive(Clone)]
+pub struct IntCode<M: Memory = Vec<i64>> {
+    pub program: M,
     pointer: usize,
-    pub input_value: i64,
-    pub first_input_done: bool,
+    inputs: VecDeque<i64>,
     pub halted: bool,
     pub outputs: Vec<i64>,
     relative_base: i64,
+    tracer: Option<trace::Tracer>,
+    budget: Option<u64>,
+    loop_guard: Option<guard::LoopGuard>,
+    arithmetic: Arithmetic,
+    watches: watch::Watches,
+    profile: Option<profile::Profile>,
+    history: Option<history::History>,
 }
 
-impl IntCode {
-    pub fn new(program: Vec<i64>, input_value: i64) -> Self {
+impl<M: Memory> IntCode<M> {
+    pub fn new(program: M) -> Self {
         IntCode {
             program,
             pointer: 0,
-            input_value,
-            first_input_done: false,
+            inputs: VecDeque::new(),
             halted: false,
             outputs: Vec::new(),
             relative_base: 0,
+            tracer: None,
+            budget: None,
+            loop_guard: None,
+            arithmetic: Arithmetic::default(),
+            watches: watch::Watches::default(),
+            profile: None,
+            history: None,
         }
     }
+
+    /// Queue a value to be read by the next Input instruction
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(value);
+    }
+
+    /// Queue several values, read in order by successive Input instructions
+    pub fn extend_inputs<I: IntoIterator<Item = i64>>(&mut self, values: I) {
+        self.inputs.extend(values);
+    }
+
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    fn overflow(&self) -> IntcodeError {
+        IntcodeError::Overflow {
+            context: self.context(),
+        }
+    }
+
+    fn context(&self) -> ErrorContext {
+        ErrorContext::at(&self.program, self.pointer, self.relative_base)
+    }
 }
 
-#[derive(FromPrimitive, Copy, Clone, Debug)]
-enum Mode {
+/// Machine state at the instruction that failed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ErrorContext {
+    pub pointer: usize,
+    pub instruction: i64,
+    pub relative_base: i64,
+}
+
+impl ErrorContext {
+    fn at<M: Memory>(program: &M, pointer: usize, relative_base: i64) -> Self {
+        ErrorContext {
+            pointer,
+            instruction: program.read(pointer),
+            relative_base,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IntcodeError {
+    UnknownOpCode { opcode: i64, context: ErrorContext },
+    BadMode { mode: i64, context: ErrorContext },
+    NegativeAddress { address: i64, context: ErrorContext },
+    WriteInImmediateMode { context: ErrorContext },
+    MissingInput { context: ErrorContext },
+    OutOfBudget { context: ErrorContext },
+    InfiniteLoop { context: ErrorContext },
+    Overflow { context: ErrorContext },
+    DependsOnUnknowns { context: ErrorContext },
+}
+
+impl IntcodeError {
+    pub fn context(&self) -> ErrorContext {
+        match *self {
+            IntcodeError::UnknownOpCode { context, .. }
+            | IntcodeError::BadMode { context, .. }
+            | IntcodeError::NegativeAddress { context, .. }
+            | IntcodeError::WriteInImmediateMode { context }
+            | IntcodeError::MissingInput { context }
+            | IntcodeError::OutOfBudget { context }
+            | IntcodeError::InfiniteLoop { context }
+            | IntcodeError::Overflow { context }
+            | IntcodeError::DependsOnUnknowns { context } => context,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpCode { opcode, .. } => write!(f, "unknown opcode {}", opcode)?,
+            IntcodeError::BadMode { mode, .. } => write!(f, "unknown parameter mode {}", mode)?,
+            IntcodeError::NegativeAddress { address, .. } => {
+                write!(f, "negative address {}", address)?
+            }
+            IntcodeError::WriteInImmediateMode { .. } => {
+                write!(f, "write parameter in immediate mode")?
+            }
+            IntcodeError::MissingInput { .. } => write!(f, "no input available")?,
+            IntcodeError::OutOfBudget { .. } => write!(f, "instruction budget used up")?,
+            IntcodeError::InfiniteLoop { .. } => write!(f, "infinite loop")?,
+            IntcodeError::Overflow { .. } => write!(f, "arithmetic overflow")?,
+            IntcodeError::DependsOnUnknowns { .. } => write!(f, "value depends on unknowns")?,
+        }
+        let context = self.context();
+        write!(
+            f,
+            " at pointer {} (instruction {}, relative base {})",
+            context.pointer, context.instruction, context.relative_base
+        )
+    }
+}
+
+impl std::error::Error for IntcodeError {}
+
+#[derive(FromPrimitive, Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Mode {
     Position = 0,
     Immediate = 1,
     Relative = 2,
@@ -38,66 +178,74 @@ impl Default for Mode {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub struct Parameter {
     value: i64,
     mode: Mode,
 }
 
 impl Parameter {
-    fn _get_address(&self, program: &[i64]) -> Result<i64, Error> {
-        if self.value < 0 {
-            Err(anyhow!("negative address"))
+    fn address<M: Memory>(&self, intcode: &IntCode<M>) -> Result<usize, IntcodeError> {
+        let address = match self.mode {
+            Mode::Relative => self
+                .value
+                .checked_add(intcode.relative_base)
+                .ok_or_else(|| intcode.overflow())?,
+            _ => self.value,
+        };
+        if address < 0 {
+            Err(IntcodeError::NegativeAddress {
+                address,
+                context: intcode.context(),
+            })
         } else {
-            let address = self.value as usize;
-            if address >= program.len() {
-                Ok(0)
-            } else {
-                Ok(program[self.value as usize])
-            }
+            Ok(address as usize)
         }
     }
 
-    fn _set_address(&self, value: i64, program: &mut Vec<i64>) -> Result<(), Error> {
-        if self.value < 0 {
-            Err(anyhow!("negative address"))
-        } else {
-            let address = self.value as usize;
-            if address >= program.len() {
-                program.resize(address + 1, 0);
-            }
-            program[address] = value;
-            Ok(())
+    /// Value of the parameter, without triggering watchpoints
+    fn peek<M: Memory>(&self, intcode: &IntCode<M>) -> Result<i64, IntcodeError> {
+        match self.mode {
+            Mode::Immediate => Ok(self.value),
+            _ => Ok(intcode.program.read(self.address(intcode)?)),
         }
     }
 
-    fn get(&mut self, program: &[i64], relative_base: i64) -> Result<i64, Error> {
-        match self.mode {
-            Mode::Position => Ok(self._get_address(program)?),
-            Mode::Immediate => Ok(self.value),
-            Mode::Relative => {
-                self.value += relative_base;
-                Ok(self._get_address(program)?)
-            }
+    fn get<M: Memory>(&self, intcode: &mut IntCode<M>) -> Result<i64, IntcodeError> {
+        if self.mode == Mode::Immediate {
+            return Ok(self.value);
+        }
+        let address = self.address(intcode)?;
+        let value = intcode.program.read(address);
+        intcode.profile_read(address);
+        if intcode.watches.is_active() {
+            intcode.notify_watches(WatchKind::Read, address, value, value);
         }
+        Ok(value)
     }
 
-    fn set(&mut self, value: i64, program: &mut Vec<i64>, relative_base: i64) -> Result<(), Error> {
+    fn set<M: Memory>(&self, value: i64, intcode: &mut IntCode<M>) -> Result<(), IntcodeError> {
         match self.mode {
-            Mode::Position => {
-                self._set_address(value, program)?;
+            Mode::Immediate => Err(IntcodeError::WriteInImmediateMode {
+                context: intcode.context(),
+            }),
+            _ => {
+                let address = self.address(intcode)?;
+                intcode.profile_write(address);
+                if intcode.watches.is_active() {
+                    let old = intcode.program.read(address);
+                    intcode.program.write(address, value);
+                    intcode.notify_watches(WatchKind::Write, address, old, value);
+                } else {
+                    intcode.program.write(address, value);
+                }
                 Ok(())
             }
-            Mode::Relative => {
-                self.value += relative_base;
-                self._set_address(value, program)
-            }
-            _ => Err(anyhow!("set parameter must be in position/relative mode")),
         }
     }
 }
 
-#[derive(FromPrimitive, Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(FromPrimitive, Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub enum OpCode {
     Add = 1,
     Multiply = 2,
@@ -120,9 +268,119 @@ impl OpCode {
             OpCode::Halt => 0,
         }
     }
+
+    /// Whether the last parameter is an address written to
+    fn writes(self) -> bool {
+        matches!(
+            self,
+            OpCode::Add | OpCode::Multiply | OpCode::Input | OpCode::LessThan | OpCode::Equals
+        )
+    }
+}
+
+/// How Add, Multiply and RelativeBaseOffset treat results that don't fit in an i64
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub enum Arithmetic {
+    /// Wrap around, the same in debug and release builds
+    #[default]
+    Wrapping,
+    /// Fail with IntcodeError::Overflow
+    Checked,
+}
+
+impl Arithmetic {
+    fn add(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_add(b)),
+            Arithmetic::Checked => a.checked_add(b),
+        }
+    }
+
+    fn multiply(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_mul(b)),
+            Arithmetic::Checked => a.checked_mul(b),
+        }
+    }
+}
+
+/// Largest number of parameters taken by any OpCode
+const MAX_PARAMETERS: usize = 3;
+
+/// A decoded instruction. Only the first `opcode.num_parameters()` parameters are meaningful.
+#[derive(Debug, Copy, Clone)]
+pub struct Instruction {
+    pub opcode: OpCode,
+    parameters: [Parameter; MAX_PARAMETERS],
+}
+
+impl Instruction {
+    /// Decode the instruction starting at `pointer`.
+    /// `relative_base` is only used to describe the machine state in errors.
+    pub fn decode<M: Memory>(
+        program: &M,
+        pointer: usize,
+        relative_base: i64,
+    ) -> Result<Instruction, IntcodeError> {
+        Instruction::decode_with(|address| program.read(address), pointer, relative_base)
+    }
+
+    /// Decode using `read` to fetch the cell at an address
+    fn decode_with<F: Fn(usize) -> i64>(
+        read: F,
+        pointer: usize,
+        relative_base: i64,
+    ) -> Result<Instruction, IntcodeError> {
+        let word = read(pointer);
+        let context = || ErrorContext {
+            pointer,
+            instruction: word,
+            relative_base,
+        };
+        let opcode: OpCode = FromPrimitive::from_i64(word % 100)
+            .filter(|_| word >= 0)
+            .ok_or_else(|| IntcodeError::UnknownOpCode {
+                opcode: word % 100,
+                context: context(),
+            })?;
+        let mut modes = word / 100;
+        let mut parameters = [Parameter::default(); MAX_PARAMETERS];
+        for (i, parameter) in parameters
+            .iter_mut()
+            .take(opcode.num_parameters())
+            .enumerate()
+        {
+            parameter.value = read(pointer + i + 1);
+            parameter.mode =
+                FromPrimitive::from_i64(modes % 10).ok_or_else(|| IntcodeError::BadMode {
+                    mode: modes % 10,
+                    context: context(),
+                })?;
+            modes /= 10;
+        }
+        Ok(Instruction { opcode, parameters })
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters[..self.opcode.num_parameters()]
+    }
 }
 
-impl IntCode {
+/// Why a call to `IntCode::run` stopped. `T` is the cell type of the machine.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RunState<T = i64> {
+    NeedsInput,
+    Output(T),
+    Halted,
+    /// The budget set with `set_budget` is used up
+    OutOfBudget,
+    /// Loop detection is on and the machine state repeated without I/O
+    InfiniteLoop,
+    /// The last instruction executed hit a pausing watchpoint
+    Watchpoint(WatchHit),
+}
+
+impl<M: Memory> IntCode<M> {
     pub fn get_last_output(&self) -> Option<i64> {
         if !self.outputs.is_empty() {
             Some(self.outputs[self.outputs.len() - 1])
@@ -131,122 +389,334 @@ impl IntCode {
         }
     }
 
-    pub fn process(&mut self) -> Result<Option<i64>, Error> {
-        while !self.step()? {}
-        Ok(self.get_last_output())
-    }
-
-    fn step(&mut self) -> Result<bool, Error> {
-        let (opcode, mut parameters) = self.make_instruction()?;
-        Ok(self.process_instruction(opcode, &mut parameters)?)
-    }
-
-    pub fn make_instruction(&mut self) -> Result<(OpCode, Vec<Parameter>), Error> {
-        if self.pointer >= self.program.len() {
-            self.program.resize(self.pointer + 1, 0);
-        }
-        let instruction: Vec<_> = self.program[self.pointer].to_string().chars().collect();
-        let opcode_length = if instruction.len() >= 2 { 2 } else { 1 };
-        let opcode_int = instruction[instruction.len() - opcode_length..]
-            .iter()
-            .collect::<String>()
-            .parse::<usize>()?;
-        let opcode: OpCode = FromPrimitive::from_usize(opcode_int)
-            .ok_or_else(|| anyhow!("Unknown OpCode {}", opcode_int))?;
-        let mut parameters = Vec::with_capacity(opcode.num_parameters());
-        for char in instruction[..instruction.len() - opcode_length]
-            .iter()
-            .rev()
-            {
-                parameters.push(Parameter {
-                    value: self.program[self.pointer + parameters.len() + 1],
-                    mode: FromPrimitive::from_u32(
-                        char.to_digit(10)
-                            .ok_or_else(|| anyhow!("char not a digit"))?,
-                    )
-                        .ok_or_else(|| anyhow!("Unknown Mode"))?,
-                });
+    pub fn process(&mut self) -> Result<Option<i64>, IntcodeError> {
+        loop {
+            match self.run()? {
+                RunState::NeedsInput => {
+                    return Err(IntcodeError::MissingInput {
+                        context: self.context(),
+                    })
+                }
+                RunState::Output(_) | RunState::Watchpoint(_) => (),
+                RunState::Halted => return Ok(self.get_last_output()),
+                RunState::OutOfBudget => {
+                    return Err(IntcodeError::OutOfBudget {
+                        context: self.context(),
+                    })
+                }
+                RunState::InfiniteLoop => {
+                    return Err(IntcodeError::InfiniteLoop {
+                        context: self.context(),
+                    })
+                }
             }
-        for _ in 0..opcode.num_parameters() - parameters.len() {
-            parameters.push(Parameter {
-                value: self.program[self.pointer + parameters.len() + 1],
-                mode: Mode::default(),
-            });
         }
-        Ok((opcode, parameters))
     }
 
-    pub fn process_instruction(
-        &mut self,
-        opcode: OpCode,
-        parameters: &mut [Parameter],
-    ) -> Result<bool, Error> {
-        assert_eq!(parameters.len(), opcode.num_parameters());
-        match opcode {
-            OpCode::Add => {
-                let op1 = parameters[0].get(&self.program, self.relative_base)?;
-                let op2 = parameters[1].get(&self.program, self.relative_base)?;
-                parameters[2].set(op1 + op2, &mut self.program, self.relative_base)?;
-                self.pointer += opcode.num_parameters() + 1;
+    /// Run until the program needs an input that isn't queued, produces an output, or halts
+    /// (or runs out of budget, or loops when loop detection is on).
+    /// Execution can be resumed by calling run again (after pushing input if needed).
+    pub fn run(&mut self) -> Result<RunState, IntcodeError> {
+        self.reset_loop_guard();
+        loop {
+            if let Some(hit) = self.watches.hit.take() {
+                return Ok(RunState::Watchpoint(hit));
             }
-            OpCode::Multiply => {
-                let op1 = parameters[0].get(&self.program, self.relative_base)?;
-                let op2 = parameters[1].get(&self.program, self.relative_base)?;
-                parameters[2].set(op1 * op2, &mut self.program, self.relative_base)?;
-                self.pointer += opcode.num_parameters() + 1;
+            if self.halted {
+                return Ok(RunState::Halted);
             }
-            OpCode::Input => {
-                parameters[0].set(self.input_value, &mut self.program, self.relative_base)?;
-                self.pointer += opcode.num_parameters() + 1;
+            if self.budget == Some(0) {
+                return Ok(RunState::OutOfBudget);
             }
-            OpCode::Output => {
-                self.outputs
-                    .push(parameters[0].get(&self.program, self.relative_base)?);
-                self.pointer += opcode.num_parameters() + 1;
+            if self.in_loop() {
+                return Ok(RunState::InfiniteLoop);
             }
-            OpCode::JumpIfTrue => {
-                if parameters[0].get(&self.program, self.relative_base)? != 0 {
-                    self.pointer = parameters[1].get(&self.program, self.relative_base)? as usize;
-                } else {
-                    self.pointer += opcode.num_parameters() + 1;
-                }
+            let instruction = self.make_instruction()?;
+            if instruction.opcode == OpCode::Input && self.inputs.is_empty() {
+                return Ok(RunState::NeedsInput);
             }
-            OpCode::JumpIfFalse => {
-                if parameters[0].get(&self.program, self.relative_base)? == 0 {
-                    self.pointer = parameters[1].get(&self.program, self.relative_base)? as usize;
-                } else {
-                    self.pointer += opcode.num_parameters() + 1;
-                }
+            let halted = self.process_instruction(&instruction)?;
+            if let Some(budget) = self.budget.as_mut() {
+                *budget -= 1;
             }
-            OpCode::LessThan => {
-                if parameters[0].get(&self.program, self.relative_base)?
-                    < parameters[1].get(&self.program, self.relative_base)?
-                {
-                    parameters[2].set(1, &mut self.program, self.relative_base)?;
-                } else {
-                    parameters[2].set(0, &mut self.program, self.relative_base)?;
-                }
-                self.pointer += opcode.num_parameters() + 1;
+            if halted {
+                return Ok(RunState::Halted);
             }
-            OpCode::Equals => {
-                if parameters[0].get(&self.program, self.relative_base)?
-                    == parameters[1].get(&self.program, self.relative_base)?
-                {
-                    parameters[2].set(1, &mut self.program, self.relative_base)?;
-                } else {
-                    parameters[2].set(0, &mut self.program, self.relative_base)?;
-                }
-                self.pointer += opcode.num_parameters() + 1;
+            if instruction.opcode == OpCode::Output {
+                return Ok(RunState::Output(self.outputs[self.outputs.len() - 1]));
             }
-            OpCode::RelativeBaseOffset => {
-                self.relative_base += parameters[0].get(&self.program, self.relative_base)?;
-                self.pointer += opcode.num_parameters() + 1;
+        }
+    }
+
+    pub fn make_instruction(&self) -> Result<Instruction, IntcodeError> {
+        Instruction::decode(&self.program, self.pointer, self.relative_base)
+    }
+
+    pub fn process_instruction(&mut self, instruction: &Instruction) -> Result<bool, IntcodeError> {
+        let record = self.trace_before(instruction);
+        let write = self.guard_before(instruction);
+        self.profile_instruction(instruction);
+        let undo = self.history_before(instruction);
+        let halted = execute(self, instruction)?;
+        self.history_after(undo);
+        self.guard_after(instruction, write);
+        self.trace_after(record);
+        Ok(halted)
+    }
+}
+
+/// A value a machine's cells can hold
+pub trait Cell: Clone + PartialEq + PartialOrd {
+    fn from_i64(value: i64) -> Self;
+    /// None if the value doesn't fit, e.g. to be used as an address
+    fn to_i64(&self) -> Option<i64>;
+    /// None on overflow
+    fn add(&self, other: &Self, arithmetic: Arithmetic) -> Option<Self>;
+    fn multiply(&self, other: &Self, arithmetic: Arithmetic) -> Option<Self>;
+}
+
+impl Cell for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn add(&self, other: &Self, arithmetic: Arithmetic) -> Option<Self> {
+        arithmetic.add(*self, *other)
+    }
+
+    fn multiply(&self, other: &Self, arithmetic: Arithmetic) -> Option<Self> {
+        arithmetic.multiply(*self, *other)
+    }
+}
+
+/// What `execute` needs from a machine, so machines with different cell types
+/// share one implementation of the instruction set
+trait Core {
+    type Cell: Cell;
+    /// Value of parameter `i` of `instruction`
+    fn get(&mut self, instruction: &Instruction, i: usize) -> Result<Self::Cell, IntcodeError>;
+    fn set(
+        &mut self,
+        instruction: &Instruction,
+        i: usize,
+        value: Self::Cell,
+    ) -> Result<(), IntcodeError>;
+    fn input(&mut self) -> Option<Self::Cell>;
+    fn output(&mut self, value: Self::Cell);
+    fn arithmetic(&self) -> Arithmetic;
+    fn pointer_mut(&mut self) -> &mut usize;
+    fn relative_base_mut(&mut self) -> &mut i64;
+    fn halt(&mut self);
+    fn context(&self) -> ErrorContext;
+}
+
+/// Carry out one instruction. Returns true if it halted the machine.
+fn execute<C: Core>(machine: &mut C, instruction: &Instruction) -> Result<bool, IntcodeError> {
+    let overflow = |machine: &C| IntcodeError::Overflow {
+        context: machine.context(),
+    };
+    let opcode = instruction.opcode;
+    let next = *machine.pointer_mut() + opcode.num_parameters() + 1;
+    match opcode {
+        OpCode::Add | OpCode::Multiply => {
+            let op1 = machine.get(instruction, 0)?;
+            let op2 = machine.get(instruction, 1)?;
+            let result = match opcode {
+                OpCode::Add => op1.add(&op2, machine.arithmetic()),
+                _ => op1.multiply(&op2, machine.arithmetic()),
             }
-            OpCode::Halt => {
-                self.halted = true;
-                return Ok(true);
+            .ok_or_else(|| overflow(machine))?;
+            machine.set(instruction, 2, result)?;
+        }
+        OpCode::LessThan | OpCode::Equals => {
+            let op1 = machine.get(instruction, 0)?;
+            let op2 = machine.get(instruction, 1)?;
+            let result = match opcode {
+                OpCode::LessThan => op1 < op2,
+                _ => op1 == op2,
+            };
+            machine.set(instruction, 2, C::Cell::from_i64(result as i64))?;
+        }
+        OpCode::Input => {
+            let value = machine.input().ok_or_else(|| IntcodeError::MissingInput {
+                context: machine.context(),
+            })?;
+            machine.set(instruction, 0, value)?;
+        }
+        OpCode::Output => {
+            let value = machine.get(instruction, 0)?;
+            machine.output(value);
+        }
+        OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+            let is_zero = machine.get(instruction, 0)? == C::Cell::from_i64(0);
+            if is_zero == (opcode == OpCode::JumpIfFalse) {
+                let target = machine.get(instruction, 1)?;
+                let target = target.to_i64().ok_or_else(|| overflow(machine))?;
+                *machine.pointer_mut() = target as usize;
+                return Ok(false);
             }
         }
-        Ok(false)
+        OpCode::RelativeBaseOffset => {
+            let offset = machine.get(instruction, 0)?;
+            let offset = offset.to_i64().ok_or_else(|| overflow(machine))?;
+            let relative_base = *machine.relative_base_mut();
+            *machine.relative_base_mut() = machine
+                .arithmetic()
+                .add(relative_base, offset)
+                .ok_or_else(|| overflow(machine))?;
+        }
+        OpCode::Halt => {
+            machine.halt();
+            return Ok(true);
+        }
+    }
+    *machine.pointer_mut() = next;
+    Ok(false)
+}
+
+impl<M: Memory> Core for IntCode<M> {
+    type Cell = i64;
+
+    fn get(&mut self, instruction: &Instruction, i: usize) -> Result<i64, IntcodeError> {
+        instruction.parameters[i].get(self)
+    }
+
+    fn set(&mut self, instruction: &Instruction, i: usize, value: i64) -> Result<(), IntcodeError> {
+        instruction.parameters[i].set(value, self)
+    }
+
+    fn input(&mut self) -> Option<i64> {
+        self.inputs.pop_front()
+    }
+
+    fn output(&mut self, value: i64) {
+        self.outputs.push(value);
+    }
+
+    fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    fn pointer_mut(&mut self) -> &mut usize {
+        &mut self.pointer
+    }
+
+    fn relative_base_mut(&mut self) -> &mut i64 {
+        &mut self.relative_base
+    }
+
+    fn halt(&mut self) {
+        self.halted = true;
+    }
+
+    fn context(&self) -> ErrorContext {
+        IntCode::context(self)
+    }
+}
+
+/// Test program that reads n, outputs n down to 1 and halts.
+/// The loop starts at 2, the halt is at 11 and the counter at 12.
+#[cfg(test)]
+fn countdown() -> Result<Vec<i64>, anyhow::Error> {
+    assembler::assemble(
+        "       IN -> [x]
+        loop:   OUT [x]
+                ADD [x], #-1 -> [x]
+                JT [x], #loop
+                HLT
+        x:      .data 0",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), IntcodeError> {
+        let intcode = IntCode::new(vec![21107, 4, -3, 5]);
+        let instruction = intcode.make_instruction()?;
+        assert_eq!(instruction.opcode, OpCode::LessThan);
+        let values: Vec<_> = instruction.parameters().iter().map(|p| p.value).collect();
+        assert_eq!(values, vec![4, -3, 5]);
+        let modes: Vec<_> = instruction.parameters().iter().map(|p| p.mode).collect();
+        assert_eq!(
+            modes,
+            vec![Mode::Immediate, Mode::Immediate, Mode::Relative]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_2() {
+        let context = |pointer, instruction, relative_base| ErrorContext {
+            pointer,
+            instruction,
+            relative_base,
+        };
+        assert_eq!(
+            IntCode::new(vec![42]).process(),
+            Err(IntcodeError::UnknownOpCode {
+                opcode: 42,
+                context: context(0, 42, 0)
+            })
+        );
+        assert_eq!(
+            IntCode::new(vec![1101, 1, 1, 5, 301, 0, 0, 0]).process(),
+            Err(IntcodeError::BadMode {
+                mode: 3,
+                context: context(4, 301, 0)
+            })
+        );
+        assert_eq!(
+            IntCode::new(vec![109, -5, 204, 1, 99]).process(),
+            Err(IntcodeError::NegativeAddress {
+                address: -4,
+                context: context(2, 204, -5)
+            })
+        );
+        // the relative address overflows even with wrapping arithmetic
+        assert_eq!(
+            IntCode::new(vec![109, i64::MAX, 204, 1, 99]).process(),
+            Err(IntcodeError::Overflow {
+                context: context(2, 204, i64::MAX)
+            })
+        );
+        assert_eq!(
+            IntCode::new(vec![11101, 1, 1, 0, 99]).process(),
+            Err(IntcodeError::WriteInImmediateMode {
+                context: context(0, 11101, 0)
+            })
+        );
+        assert_eq!(
+            IntCode::new(vec![3, 0, 99]).process(),
+            Err(IntcodeError::MissingInput {
+                context: context(0, 3, 0)
+            })
+        );
+    }
+
+    #[test]
+    fn test_3() -> Result<(), IntcodeError> {
+        let program = vec![1102, i64::MAX, 2, 7, 4, 7, 99, 0];
+        let mut intcode = IntCode::new(program.clone());
+        assert_eq!(intcode.process()?, Some(-2));
+        let mut intcode = IntCode::new(program);
+        intcode.set_arithmetic(Arithmetic::Checked);
+        assert_eq!(
+            intcode.process(),
+            Err(IntcodeError::Overflow {
+                context: ErrorContext {
+                    pointer: 0,
+                    instruction: 1102,
+                    relative_base: 0
+                }
+            })
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,143 @@
+//! Text I/O for programs that talk in ASCII codes, one character per value
+use anyhow::Error;
+
+use crate::intcode_compiler::device::OutputSink;
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::{IntCode, IntcodeError, RunState};
+
+const NEWLINE: i64 = '\n' as i64;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AsciiOutput {
+    /// A line of text, without its newline
+    Line(String),
+    /// An output outside the ASCII range, such as a puzzle answer
+    Value(i64),
+}
+
+/// Collects output into lines of text.
+/// A non-ASCII value is reported as soon as it arrives, even in the middle of a line.
+#[derive(Clone, Debug, Default)]
+pub struct Terminal {
+    partial: String,
+    pub output: Vec<AsciiOutput>,
+}
+
+impl Terminal {
+    pub fn print(&mut self, value: i64) {
+        match value {
+            NEWLINE => self
+                .output
+                .push(AsciiOutput::Line(std::mem::take(&mut self.partial))),
+            0..=127 => self.partial.push(value as u8 as char),
+            _ => self.output.push(AsciiOutput::Value(value)),
+        }
+    }
+
+    /// Text printed since the last newline, e.g. a prompt
+    pub fn partial(&self) -> &str {
+        &self.partial
+    }
+
+    /// The complete lines received so far
+    pub fn lines(&self) -> Vec<&str> {
+        self.output
+            .iter()
+            .filter_map(|output| match output {
+                AsciiOutput::Line(line) => Some(line.as_str()),
+                AsciiOutput::Value(_) => None,
+            })
+            .collect()
+    }
+
+    /// The non-ASCII values received so far
+    pub fn values(&self) -> Vec<i64> {
+        self.output
+            .iter()
+            .filter_map(|output| match output {
+                AsciiOutput::Value(value) => Some(*value),
+                AsciiOutput::Line(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl OutputSink for Terminal {
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        self.print(value);
+        Ok(())
+    }
+}
+
+/// ASCII codes of `line` followed by a newline
+pub fn encode(line: &str) -> Vec<i64> {
+    line.chars()
+        .map(|c| c as i64)
+        .chain(std::iter::once(NEWLINE))
+        .collect()
+}
+
+impl<M: Memory> IntCode<M> {
+    /// Queue a line of text as input, adding the newline
+    pub fn push_line(&mut self, line: &str) {
+        self.extend_inputs(encode(line));
+    }
+
+    /// Run, printing every output to `terminal`,
+    /// until the program needs input or stops for another reason
+    pub fn run_ascii(&mut self, terminal: &mut Terminal) -> Result<RunState, IntcodeError> {
+        loop {
+            match self.run()? {
+                RunState::Output(value) => terminal.print(value),
+                state => return Ok(state),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode_compiler::assembler::assemble;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        // echoes its input, following each line with 1000 plus the line length
+        let program = assemble(
+            "loop:   IN -> [c]
+                    OUT [c]
+                    EQ [c], #10 -> [t]
+                    JT [t], #done
+                    ADD [n], #1 -> [n]
+                    JT #1, #loop
+            done:   ADD [n], #1000 -> [t]
+                    OUT [t]
+                    ADD #0, #0 -> [n]
+                    JT #1, #loop
+            c:      .data 0
+            t:      .data 0
+            n:      .data 0",
+        )?;
+        let mut intcode = IntCode::new(program);
+        let mut terminal = Terminal::default();
+        intcode.push_line("north");
+        assert_eq!(intcode.run_ascii(&mut terminal)?, RunState::NeedsInput);
+        assert_eq!(
+            terminal.output,
+            vec![
+                AsciiOutput::Line("north".to_owned()),
+                AsciiOutput::Value(1005)
+            ]
+        );
+
+        let long = "x".repeat(200);
+        intcode.push_line(&long);
+        intcode.extend_inputs(vec![105, 110, 118]);
+        intcode.run_ascii(&mut terminal)?;
+        assert_eq!(terminal.lines(), vec!["north", long.as_str()]);
+        assert_eq!(terminal.values(), vec![1005, 1200]);
+        assert_eq!(terminal.partial(), "inv");
+        Ok(())
+    }
+}
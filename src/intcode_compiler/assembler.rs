@@ -0,0 +1,290 @@
+//! A small assembly language for Intcode, using the same syntax as the disassembler:
+//!
+//! ```text
+//! ; read a number and print it plus one
+//! start:  IN -> [x]
+//!         ADD [x], #1 -> [rb+2]
+//!         OUT [rb+2]
+//!         JT #1, #start
+//! x:      .data 0
+//! ```
+//!
+//! Parameters are `#n` (immediate), `[n]` or `n` (position) and `[rb+n]` (relative),
+//! where `n` is a number or a label. The written parameter may be separated by `->` or `,`.
+//! `.data` (or `DATA`) emits its comma-separated values as-is.
+//! A numeric prefix like `0012:` asserts the address of the line, so disassembler output can be reassembled.
+use std::collections::HashMap;
+
+use anyhow::Error;
+
+use crate::intcode_compiler::{Mode, OpCode};
+
+impl OpCode {
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        match mnemonic.to_uppercase().as_str() {
+            "ADD" => Some(OpCode::Add),
+            "MUL" => Some(OpCode::Multiply),
+            "IN" => Some(OpCode::Input),
+            "OUT" => Some(OpCode::Output),
+            "JT" => Some(OpCode::JumpIfTrue),
+            "JF" => Some(OpCode::JumpIfFalse),
+            "LT" => Some(OpCode::LessThan),
+            "EQ" => Some(OpCode::Equals),
+            "ARB" => Some(OpCode::RelativeBaseOffset),
+            "HLT" => Some(OpCode::Halt),
+            _ => None,
+        }
+    }
+}
+
+/// A number or a label, negated for `[rb-label]`
+#[derive(Debug, Copy, Clone)]
+struct Expression<'a> {
+    text: &'a str,
+    sign: i64,
+}
+
+impl<'a> Expression<'a> {
+    fn parse(text: &'a str) -> Result<Self, Error> {
+        let text = text.trim();
+        if text.is_empty() {
+            Err(anyhow!("missing value"))
+        } else {
+            Ok(Expression { text, sign: 1 })
+        }
+    }
+
+    fn resolve(&self, labels: &HashMap<&str, usize>) -> Result<i64, Error> {
+        let value = match self.text.parse::<i64>() {
+            Ok(value) => value,
+            Err(_) => *labels
+                .get(self.text)
+                .ok_or_else(|| anyhow!("unknown label {}", self.text))?
+                as i64,
+        };
+        Ok(self.sign * value)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Operand<'a> {
+    mode: Mode,
+    value: Expression<'a>,
+}
+
+impl<'a> Operand<'a> {
+    fn parse(text: &'a str) -> Result<Self, Error> {
+        let text = text.trim();
+        if let Some(value) = text.strip_prefix('#') {
+            return Ok(Operand {
+                mode: Mode::Immediate,
+                value: Expression::parse(value)?,
+            });
+        }
+        let inner = match text.strip_prefix('[') {
+            Some(inner) => inner
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("unclosed bracket in {}", text))?
+                .trim(),
+            None => {
+                return Ok(Operand {
+                    mode: Mode::Position,
+                    value: Expression::parse(text)?,
+                })
+            }
+        };
+        match inner.strip_prefix("rb") {
+            Some("") => Ok(Operand {
+                mode: Mode::Relative,
+                value: Expression::parse("0")?,
+            }),
+            Some(offset) => {
+                let offset = offset.trim_start();
+                let (sign, value) = if let Some(value) = offset.strip_prefix('+') {
+                    (1, value)
+                } else if let Some(value) = offset.strip_prefix('-') {
+                    (-1, value)
+                } else {
+                    return Err(anyhow!("expected + or - after rb in {}", text));
+                };
+                let mut value = Expression::parse(value)?;
+                value.sign = sign;
+                Ok(Operand {
+                    mode: Mode::Relative,
+                    value,
+                })
+            }
+            None => Ok(Operand {
+                mode: Mode::Position,
+                value: Expression::parse(inner)?,
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Statement<'a> {
+    Instruction(OpCode, Vec<Operand<'a>>),
+    Data(Vec<Expression<'a>>),
+}
+
+impl<'a> Statement<'a> {
+    fn parse(text: &'a str) -> Result<Self, Error> {
+        let mut parts = text.splitn(2, char::is_whitespace);
+        let mnemonic = parts.next().unwrap_or("");
+        let rest = parts.next().unwrap_or("").trim();
+        // single-parameter writes have nothing before the arrow, e.g. `IN -> [x]`
+        let rest = rest.strip_prefix("->").unwrap_or(rest);
+        let arguments: Vec<_> = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split("->")
+                .flat_map(|part| part.split(','))
+                .map(str::trim)
+                .collect()
+        };
+        if mnemonic == ".data" || mnemonic == "DATA" {
+            return Ok(Statement::Data(
+                arguments
+                    .into_iter()
+                    .map(Expression::parse)
+                    .collect::<Result<_, _>>()?,
+            ));
+        }
+        let opcode = OpCode::from_mnemonic(mnemonic)
+            .ok_or_else(|| anyhow!("unknown mnemonic {}", mnemonic))?;
+        if arguments.len() != opcode.num_parameters() {
+            return Err(anyhow!(
+                "{} takes {} parameters, found {}",
+                mnemonic,
+                opcode.num_parameters(),
+                arguments.len()
+            ));
+        }
+        let operands = arguments
+            .into_iter()
+            .map(Operand::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if let (true, Some(operand)) = (opcode.writes(), operands.last()) {
+            if operand.mode == Mode::Immediate {
+                return Err(anyhow!(
+                    "{} cannot write to an immediate parameter",
+                    mnemonic
+                ));
+            }
+        }
+        Ok(Statement::Instruction(opcode, operands))
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Statement::Instruction(opcode, _) => opcode.num_parameters() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+
+    fn emit(&self, labels: &HashMap<&str, usize>, program: &mut Vec<i64>) -> Result<(), Error> {
+        match self {
+            Statement::Instruction(opcode, operands) => {
+                let modes = operands
+                    .iter()
+                    .rev()
+                    .fold(0, |modes, operand| modes * 10 + operand.mode as i64);
+                program.push(*opcode as i64 + 100 * modes);
+                for operand in operands {
+                    program.push(operand.value.resolve(labels)?);
+                }
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    program.push(value.resolve(labels)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Assemble source text into a program ready for `IntCode::new`
+pub fn assemble(source: &str) -> Result<Vec<i64>, Error> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+    for (line_number, line) in source.lines().enumerate() {
+        let context = |error: Error| anyhow!("line {}: {}", line_number + 1, error);
+        let mut line = line.split(';').next().unwrap_or("").trim();
+        if let Some(index) = line.find(':') {
+            let label = line[..index].trim();
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != address {
+                    return Err(context(anyhow!(
+                        "address {} does not match position {}",
+                        expected,
+                        address
+                    )));
+                }
+            } else if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(context(anyhow!("invalid label {}", label)));
+            } else if labels.insert(label, address).is_some() {
+                return Err(context(anyhow!("duplicate label {}", label)));
+            }
+            line = line[index + 1..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let statement = Statement::parse(line).map_err(context)?;
+        address += statement.len();
+        statements.push((line_number, statement));
+    }
+    let mut program = Vec::with_capacity(address);
+    for (line_number, statement) in statements {
+        statement
+            .emit(&labels, &mut program)
+            .map_err(|error| anyhow!("line {}: {}", line_number + 1, error))?;
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode_compiler::disassembler::disassemble;
+    use crate::intcode_compiler::IntCode;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = assemble(
+            "; count down from the input, printing each value
+                    IN -> [counter]
+            loop:   OUT [counter]
+                    ADD [counter], #-1 -> [counter]
+                    JT [counter], #loop
+                    HLT
+            counter: .data 0",
+        )?;
+        assert_eq!(
+            program,
+            vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0]
+        );
+        let mut intcode = IntCode::new(program);
+        intcode.push_input(3);
+        intcode.process()?;
+        assert_eq!(intcode.outputs, vec![3, 2, 1]);
+
+        let program = vec![21001, 12, 3, 4, 109, 7, 1005, 7, 0, 204, -1, 99, 123, 1];
+        assert_eq!(assemble(&disassemble(&program))?, program);
+        Ok(())
+    }
+
+    #[test]
+    fn test_2() {
+        assert!(assemble("FOO [1]").is_err());
+        assert!(assemble("ADD [1], [2]").is_err());
+        assert!(assemble("ADD [1], [2] -> #3").is_err());
+        assert!(assemble("JT #1, #nowhere").is_err());
+        assert!(assemble("a: HLT\na: HLT").is_err());
+        assert!(assemble("0001: HLT").is_err());
+    }
+}
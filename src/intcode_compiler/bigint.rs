@@ -0,0 +1,245 @@
+//! An Intcode machine with arbitrary-precision cells, for programs whose values go past 64 bits.
+//! Addresses and the relative base still have to fit in an i64.
+use std::collections::VecDeque;
+
+use num::{BigInt, Zero};
+
+use crate::intcode_compiler::{
+    execute, Arithmetic, Cell, Core, ErrorContext, Instruction, IntcodeError, Mode, OpCode,
+    RunState,
+};
+
+pub struct BigIntCode {
+    pub program: Vec<BigInt>,
+    pointer: usize,
+    inputs: VecDeque<BigInt>,
+    pub halted: bool,
+    pub outputs: Vec<BigInt>,
+    relative_base: i64,
+}
+
+impl BigIntCode {
+    pub fn new(program: Vec<BigInt>) -> Self {
+        BigIntCode {
+            program,
+            pointer: 0,
+            inputs: VecDeque::new(),
+            halted: false,
+            outputs: Vec::new(),
+            relative_base: 0,
+        }
+    }
+
+    pub fn push_input(&mut self, value: BigInt) {
+        self.inputs.push_back(value);
+    }
+
+    pub fn get_last_output(&self) -> Option<&BigInt> {
+        self.outputs.last()
+    }
+
+    fn context(&self) -> ErrorContext {
+        ErrorContext {
+            pointer: self.pointer,
+            instruction: self.read(self.pointer).to_i64().unwrap_or(0),
+            relative_base: self.relative_base,
+        }
+    }
+
+    fn overflow(&self) -> IntcodeError {
+        IntcodeError::Overflow {
+            context: self.context(),
+        }
+    }
+
+    fn read(&self, address: usize) -> BigInt {
+        self.program
+            .get(address)
+            .cloned()
+            .unwrap_or_else(BigInt::zero)
+    }
+
+    /// Decode the opcode and modes with the i64 decoder.
+    /// Parameter values are read at full width when the instruction runs.
+    fn make_instruction(&self) -> Result<Instruction, IntcodeError> {
+        let word = self.read(self.pointer);
+        let word = word.to_i64().ok_or_else(|| IntcodeError::UnknownOpCode {
+            opcode: (&word % BigInt::from(100)).to_i64().unwrap_or(0),
+            context: self.context(),
+        })?;
+        let pointer = self.pointer;
+        Instruction::decode_with(
+            |address| if address == pointer { word } else { 0 },
+            pointer,
+            self.relative_base,
+        )
+    }
+
+    /// Address parameter `i` of the current instruction points to
+    fn address(&self, mode: Mode, i: usize) -> Result<usize, IntcodeError> {
+        let value = self
+            .read(self.pointer + i + 1)
+            .to_i64()
+            .ok_or_else(|| self.overflow())?;
+        let address = match mode {
+            Mode::Relative => value
+                .checked_add(self.relative_base)
+                .ok_or_else(|| self.overflow())?,
+            _ => value,
+        };
+        if address < 0 {
+            Err(IntcodeError::NegativeAddress {
+                address,
+                context: self.context(),
+            })
+        } else {
+            Ok(address as usize)
+        }
+    }
+
+    pub fn process(&mut self) -> Result<Option<BigInt>, IntcodeError> {
+        loop {
+            match self.run()? {
+                RunState::Output(_) => (),
+                RunState::Halted => return Ok(self.get_last_output().cloned()),
+                _ => {
+                    return Err(IntcodeError::MissingInput {
+                        context: self.context(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Same contract as `IntCode::run`
+    pub fn run(&mut self) -> Result<RunState<BigInt>, IntcodeError> {
+        loop {
+            if self.halted {
+                return Ok(RunState::Halted);
+            }
+            let instruction = self.make_instruction()?;
+            if instruction.opcode == OpCode::Input && self.inputs.is_empty() {
+                return Ok(RunState::NeedsInput);
+            }
+            if execute(self, &instruction)? {
+                return Ok(RunState::Halted);
+            }
+            if instruction.opcode == OpCode::Output {
+                return Ok(RunState::Output(
+                    self.outputs[self.outputs.len() - 1].clone(),
+                ));
+            }
+        }
+    }
+}
+
+impl Cell for BigInt {
+    fn from_i64(value: i64) -> Self {
+        BigInt::from(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        num::ToPrimitive::to_i64(self)
+    }
+
+    /// Never overflows
+    fn add(&self, other: &Self, _: Arithmetic) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn multiply(&self, other: &Self, _: Arithmetic) -> Option<Self> {
+        Some(self * other)
+    }
+}
+
+impl Core for BigIntCode {
+    type Cell = BigInt;
+
+    fn get(&mut self, instruction: &Instruction, i: usize) -> Result<BigInt, IntcodeError> {
+        match instruction.parameters[i].mode {
+            Mode::Immediate => Ok(self.read(self.pointer + i + 1)),
+            mode => Ok(self.read(self.address(mode, i)?)),
+        }
+    }
+
+    fn set(
+        &mut self,
+        instruction: &Instruction,
+        i: usize,
+        value: BigInt,
+    ) -> Result<(), IntcodeError> {
+        let address = match instruction.parameters[i].mode {
+            Mode::Immediate => {
+                return Err(IntcodeError::WriteInImmediateMode {
+                    context: self.context(),
+                })
+            }
+            mode => self.address(mode, i)?,
+        };
+        if address >= self.program.len() {
+            self.program.resize(address + 1, BigInt::zero());
+        }
+        self.program[address] = value;
+        Ok(())
+    }
+
+    fn input(&mut self) -> Option<BigInt> {
+        self.inputs.pop_front()
+    }
+
+    fn output(&mut self, value: BigInt) {
+        self.outputs.push(value);
+    }
+
+    /// Only the relative base is limited to an i64
+    fn arithmetic(&self) -> Arithmetic {
+        Arithmetic::Checked
+    }
+
+    fn pointer_mut(&mut self) -> &mut usize {
+        &mut self.pointer
+    }
+
+    fn relative_base_mut(&mut self) -> &mut i64 {
+        &mut self.relative_base
+    }
+
+    fn halt(&mut self) {
+        self.halted = true;
+    }
+
+    fn context(&self) -> ErrorContext {
+        BigIntCode::context(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::intcode_compiler::loader::parse_program;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program: Vec<BigInt> = vec![1102, i64::MAX, 4, 11, 2, 11, 11, 12, 4, 12, 99]
+            .into_iter()
+            .map(BigInt::from)
+            .collect();
+        let mut intcode = BigIntCode::new(program);
+        let product = BigInt::from(i64::MAX) * 4;
+        let expected = &product * &product;
+        assert_eq!(intcode.process()?, Some(expected));
+
+        let program: Vec<_> =
+            parse_program("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99")?
+                .into_iter()
+                .map(BigInt::from)
+                .collect();
+        let mut intcode = BigIntCode::new(program.clone());
+        intcode.process()?;
+        assert_eq!(intcode.outputs, program);
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+//! Static control-flow graph of an Intcode program.
+//! Only code reachable from address 0 (or the given entry points)
+//! through Immediate-mode jump targets is found,
+//! and the program is assumed not to modify its own instructions.
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::intcode_compiler::{Instruction, Mode, OpCode};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EdgeKind {
+    /// Execution runs on into the next block
+    Next,
+    /// A conditional jump whose condition held
+    Taken,
+    /// A conditional jump whose condition failed
+    NotTaken,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+/// Straight-line run of instructions, entered only at the first and left only after the last
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<(usize, Instruction)>,
+    pub successors: Vec<Edge>,
+    /// The block ends in a jump whose target is read from memory
+    pub unresolved: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<usize, Block>,
+    /// Reachable addresses that don't hold a valid instruction
+    pub invalid: BTreeSet<usize>,
+}
+
+/// Where control can go after the instruction at `pointer`, and whether it jumps indirectly
+fn successors(pointer: usize, instruction: &Instruction) -> (Vec<Edge>, bool) {
+    let next = Edge {
+        target: pointer + instruction.opcode.num_parameters() + 1,
+        kind: EdgeKind::Next,
+    };
+    let jump_if = match instruction.opcode {
+        OpCode::JumpIfTrue => true,
+        OpCode::JumpIfFalse => false,
+        OpCode::Halt => return (Vec::new(), false),
+        _ => return (vec![next], false),
+    };
+    let (condition, target) = (instruction.parameters[0], instruction.parameters[1]);
+    // an Immediate condition always or never jumps
+    let (may_jump, may_fall) = match condition.mode {
+        Mode::Immediate => {
+            let jumps = (condition.value != 0) == jump_if;
+            (jumps, !jumps)
+        }
+        _ => (true, true),
+    };
+    let mut edges = Vec::new();
+    let mut unresolved = false;
+    if may_jump {
+        if target.mode == Mode::Immediate && target.value >= 0 {
+            edges.push(Edge {
+                target: target.value as usize,
+                kind: EdgeKind::Taken,
+            });
+        } else {
+            unresolved = true;
+        }
+    }
+    if may_fall {
+        edges.push(Edge {
+            kind: EdgeKind::NotTaken,
+            ..next
+        });
+    }
+    (edges, unresolved)
+}
+
+impl ControlFlowGraph {
+    pub fn build(program: &[i64]) -> Self {
+        ControlFlowGraph::build_from(program, &[0])
+    }
+
+    /// Graph of the code reachable from any of `entries`,
+    /// e.g. return addresses the program jumps back to indirectly
+    pub fn build_from(program: &[i64], entries: &[usize]) -> Self {
+        let read = |address: usize| program.get(address).copied().unwrap_or(0);
+        let mut graph = ControlFlowGraph::default();
+        let mut instructions = BTreeMap::new();
+        let mut leaders: BTreeSet<_> = entries.iter().copied().collect();
+        let mut pending = entries.to_vec();
+        while let Some(pointer) = pending.pop() {
+            if instructions.contains_key(&pointer) || graph.invalid.contains(&pointer) {
+                continue;
+            }
+            let instruction = match Instruction::decode_with(read, pointer, 0) {
+                Ok(instruction)
+                    if pointer + instruction.opcode.num_parameters() < program.len() =>
+                {
+                    instruction
+                }
+                _ => {
+                    graph.invalid.insert(pointer);
+                    continue;
+                }
+            };
+            let (edges, unresolved) = successors(pointer, &instruction);
+            let ends_block = unresolved || edges.iter().any(|edge| edge.kind != EdgeKind::Next);
+            for edge in &edges {
+                if ends_block {
+                    leaders.insert(edge.target);
+                }
+                pending.push(edge.target);
+            }
+            instructions.insert(pointer, (instruction, edges, unresolved));
+        }
+        // an invalid address ends the block running into it
+        leaders.extend(graph.invalid.iter().copied());
+
+        for &start in leaders.iter().filter(|l| instructions.contains_key(l)) {
+            let mut block = Block {
+                start,
+                instructions: Vec::new(),
+                successors: Vec::new(),
+                unresolved: false,
+            };
+            let mut pointer = start;
+            while let Some((instruction, edges, unresolved)) = instructions.get(&pointer) {
+                block.instructions.push((pointer, *instruction));
+                block.successors = edges.clone();
+                block.unresolved = *unresolved;
+                match edges.as_slice() {
+                    [Edge {
+                        target,
+                        kind: EdgeKind::Next,
+                    }] if !unresolved && !leaders.contains(target) => pointer = *target,
+                    _ => break,
+                }
+            }
+            graph.blocks.insert(start, block);
+        }
+        graph
+    }
+
+    /// Graphviz source with one node per block. Unresolved jumps lead to a dashed `?` node.
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec![
+            "digraph intcode {".to_owned(),
+            "    node [shape=box, fontname=monospace];".to_owned(),
+        ];
+        for block in self.blocks.values() {
+            let label: String = block
+                .instructions
+                .iter()
+                .map(|(pointer, instruction)| format!("{:04}: {}\\l", pointer, instruction))
+                .collect();
+            lines.push(format!("    b{} [label=\"{}\"];", block.start, label));
+            for edge in &block.successors {
+                let attributes = match edge.kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Taken => " [label=\"T\"]",
+                    EdgeKind::NotTaken => " [label=\"F\"]",
+                };
+                lines.push(format!(
+                    "    b{} -> b{}{};",
+                    block.start, edge.target, attributes
+                ));
+            }
+            if block.unresolved {
+                lines.push(format!(
+                    "    u{} [label=\"?\", shape=circle, style=dashed];",
+                    block.start
+                ));
+                lines.push(format!(
+                    "    b{} -> u{} [label=\"T\", style=dashed];",
+                    block.start, block.start
+                ));
+            }
+        }
+        for address in &self.invalid {
+            lines.push(format!(
+                "    b{} [label=\"{:04}: invalid\", color=red];",
+                address, address
+            ));
+        }
+        lines.push("}".to_owned());
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::intcode_compiler::assembler::assemble;
+    use crate::intcode_compiler::countdown;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = countdown()?;
+        let graph = ControlFlowGraph::build(&program);
+        let starts: Vec<_> = graph.blocks.keys().copied().collect();
+        assert_eq!(starts, vec![0, 2, 11]);
+        assert_eq!(graph.blocks[&2].instructions.len(), 3);
+        let edges = |start| -> Vec<_> {
+            graph.blocks[&start]
+                .successors
+                .iter()
+                .map(|edge| (edge.target, edge.kind))
+                .collect()
+        };
+        assert_eq!(edges(0), vec![(2, EdgeKind::Next)]);
+        assert_eq!(
+            edges(2),
+            vec![(2, EdgeKind::Taken), (11, EdgeKind::NotTaken)]
+        );
+        assert!(edges(11).is_empty());
+        assert!(graph.invalid.is_empty());
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph intcode {
+    node [shape=box, fontname=monospace];
+    b0 [label="0000: IN -> [12]\l"];
+    b0 -> b2;
+    b2 [label="0002: OUT [12]\l0004: ADD [12], #-1 -> [12]\l0008: JT [12], #2\l"];
+    b2 -> b2 [label="T"];
+    b2 -> b11 [label="F"];
+    b11 [label="0011: HLT\l"];
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_2() -> Result<(), Error> {
+        // an unconditional jump over data, then a return through a stored address
+        let program = assemble(
+            "       JT #1, #start
+            ret:    .data 0
+            start:  ADD #done, #0 -> [ret]
+                    JF #0, [ret]
+                    .data 42
+            done:   HLT",
+        )?;
+        let graph = ControlFlowGraph::build(&program);
+        let starts: Vec<_> = graph.blocks.keys().copied().collect();
+        assert_eq!(starts, vec![0, 4]);
+        assert_eq!(
+            graph.blocks[&0].successors,
+            vec![Edge {
+                target: 4,
+                kind: EdgeKind::Taken
+            }]
+        );
+        assert!(graph.blocks[&4].unresolved);
+        assert!(graph.blocks[&4].successors.is_empty());
+        assert!(graph
+            .to_dot()
+            .contains("b4 -> u4 [label=\"T\", style=dashed];"));
+
+        let graph = ControlFlowGraph::build(&[1105, 1, 4, 99, 42]);
+        assert_eq!(graph.invalid, vec![4].into_iter().collect());
+        assert!(graph
+            .to_dot()
+            .contains("b4 [label=\"0004: invalid\", color=red];"));
+        Ok(())
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use anyhow::Error;
+
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::watch::{WatchHit, WatchKind};
+use crate::intcode_compiler::{Instruction, IntCode, IntcodeError, OpCode};
+
+const HELP: &str = "\
+s, step [n]          execute n instructions (default 1)
+c, continue          run until a breakpoint, an input request or halt
+b, break <addr|OP>   toggle a breakpoint on an address or an opcode mnemonic (e.g. b OUT)
+bl                   list breakpoints
+wa <kind> <addr> [n] pause when n cells from addr are read (r), written (w) or changed (c)
+uw <id>              remove a watchpoint
+rec                  start or stop recording history for rs and rc
+rs [n]               undo the last n instructions (default 1)
+rc <addr>            undo back to just before the last write to addr
+x <addr> [n]         print n memory cells starting at addr
+w <addr> <value>     write a value to memory
+pc <addr>            set the instruction pointer
+rb <value>           set the relative base
+in <value>...        queue input values
+r, regs              print pointer, relative base, inputs and outputs
+h, help              print this message
+q, quit              leave the debugger";
+
+/// Why stepping or continuing stopped
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint,
+    Watchpoint(WatchHit),
+    NeedsInput,
+    Halted,
+}
+
+/// Interactive debugger wrapping an IntCode machine
+pub struct Debugger<M: Memory = Vec<i64>> {
+    pub intcode: IntCode<M>,
+    address_breakpoints: HashSet<usize>,
+    opcode_breakpoints: HashSet<OpCode>,
+}
+
+impl<M: Memory> Debugger<M> {
+    pub fn new(intcode: IntCode<M>) -> Self {
+        Debugger {
+            intcode,
+            address_breakpoints: HashSet::new(),
+            opcode_breakpoints: HashSet::new(),
+        }
+    }
+
+    /// Starts recording history on the machine, so execution can be reversed.
+    /// Every instruction executed is kept until the debugger is dropped or `rec` turns it off.
+    pub fn with_history(mut intcode: IntCode<M>) -> Self {
+        intcode.start_history();
+        Debugger::new(intcode)
+    }
+
+    pub fn toggle_address_breakpoint(&mut self, address: usize) {
+        if !self.address_breakpoints.remove(&address) {
+            self.address_breakpoints.insert(address);
+        }
+    }
+
+    pub fn toggle_opcode_breakpoint(&mut self, opcode: OpCode) {
+        if !self.opcode_breakpoints.remove(&opcode) {
+            self.opcode_breakpoints.insert(opcode);
+        }
+    }
+
+    /// Execute a single instruction, unless the machine has halted or is waiting for input
+    pub fn step(&mut self) -> Result<Stop, IntcodeError> {
+        if self.intcode.halted {
+            return Ok(Stop::Halted);
+        }
+        let instruction = self.intcode.make_instruction()?;
+        if instruction.opcode == OpCode::Input && self.intcode.inputs.is_empty() {
+            return Ok(Stop::NeedsInput);
+        }
+        let halted = self.intcode.process_instruction(&instruction)?;
+        if let Some(hit) = self.intcode.watches.hit.take() {
+            return Ok(Stop::Watchpoint(hit));
+        }
+        if halted {
+            return Ok(Stop::Halted);
+        }
+        Ok(Stop::Stepped)
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.address_breakpoints.contains(&self.intcode.pointer)
+            || self
+                .intcode
+                .make_instruction()
+                .map(|instruction| self.opcode_breakpoints.contains(&instruction.opcode))
+                .unwrap_or(false)
+    }
+
+    /// Run until the next instruction is on a breakpoint, input is needed or the machine halts.
+    /// Always executes at least one instruction, so continuing from a breakpoint makes progress.
+    pub fn resume(&mut self) -> Result<Stop, IntcodeError> {
+        loop {
+            match self.step()? {
+                Stop::Stepped if self.at_breakpoint() => return Ok(Stop::Breakpoint),
+                Stop::Stepped => (),
+                stop => return Ok(stop),
+            }
+        }
+    }
+
+    /// The decoded instruction at the pointer, as shown after every command
+    pub fn current(&self) -> String {
+        let pointer = self.intcode.pointer;
+        let instruction =
+            match Instruction::decode(&self.intcode.program, pointer, self.intcode.relative_base) {
+                Ok(instruction) => instruction.to_string(),
+                Err(_) => format!("DATA {}", self.intcode.program.read(pointer)),
+            };
+        format!(
+            "{:04}: {:<32} rb={}",
+            pointer, instruction, self.intcode.relative_base
+        )
+    }
+
+    fn registers(&self) -> String {
+        format!(
+            "pointer={} rb={} halted={} inputs={:?} outputs={:?}",
+            self.intcode.pointer,
+            self.intcode.relative_base,
+            self.intcode.halted,
+            self.intcode.inputs,
+            self.intcode.outputs
+        )
+    }
+
+    fn breakpoints(&self) -> String {
+        let mut addresses: Vec<_> = self.address_breakpoints.iter().collect();
+        addresses.sort();
+        let mut opcodes: Vec<_> = self
+            .opcode_breakpoints
+            .iter()
+            .map(|opcode| opcode.mnemonic())
+            .collect();
+        opcodes.sort();
+        format!("addresses: {:?} opcodes: {:?}", addresses, opcodes)
+    }
+
+    fn memory(&self, start: usize, count: usize) -> String {
+        (start..start + count)
+            .map(|address| format!("{:04}: {}", address, self.intcode.program.read(address)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn report(&self, stop: Stop, outputs_before: usize) -> String {
+        let mut lines: Vec<_> = self.intcode.outputs[outputs_before..]
+            .iter()
+            .map(|output| format!("output: {}", output))
+            .collect();
+        match stop {
+            Stop::Stepped => (),
+            Stop::Breakpoint => lines.push("breakpoint".to_owned()),
+            Stop::Watchpoint(hit) => lines.push(format!(
+                "watchpoint {}: {:?} [{}] {} -> {}",
+                hit.id, hit.kind, hit.address, hit.old, hit.new
+            )),
+            Stop::NeedsInput => lines.push("waiting for input (use `in <value>`)".to_owned()),
+            Stop::Halted => lines.push("halted".to_owned()),
+        }
+        lines.join("\n")
+    }
+
+    /// Run one command line, returning the text to show.
+    /// Returns None when the user asks to quit.
+    pub fn execute(&mut self, command: &str) -> Result<Option<String>, Error> {
+        let words: Vec<_> = command.split_whitespace().collect();
+        let argument = |index: usize| -> Result<i64, Error> {
+            let word = words
+                .get(index)
+                .ok_or_else(|| anyhow!("missing argument {}", index))?;
+            Ok(word.parse::<i64>()?)
+        };
+        let address = |index: usize| -> Result<usize, Error> {
+            let value = argument(index)?;
+            if value < 0 {
+                Err(anyhow!("negative address {}", value))
+            } else {
+                Ok(value as usize)
+            }
+        };
+        let outputs_before = self.intcode.outputs.len();
+        let text = match words.first().copied().unwrap_or("") {
+            "" => String::new(),
+            "s" | "step" => {
+                let count = if words.len() > 1 { address(1)? } else { 1 };
+                let mut stop = Stop::Stepped;
+                for _ in 0..count {
+                    stop = self.step()?;
+                    if stop != Stop::Stepped {
+                        break;
+                    }
+                }
+                self.report(stop, outputs_before)
+            }
+            "c" | "continue" => {
+                let stop = self.resume()?;
+                self.report(stop, outputs_before)
+            }
+            "b" | "break" => {
+                let target = words.get(1).ok_or_else(|| anyhow!("missing breakpoint"))?;
+                match OpCode::from_mnemonic(target) {
+                    Some(opcode) => self.toggle_opcode_breakpoint(opcode),
+                    None => self.toggle_address_breakpoint(address(1)?),
+                }
+                self.breakpoints()
+            }
+            "bl" => self.breakpoints(),
+            "wa" => {
+                let kind = match words.get(1).copied() {
+                    Some("r") => WatchKind::Read,
+                    Some("w") => WatchKind::Write,
+                    Some("c") => WatchKind::Change,
+                    _ => return Err(anyhow!("watch kind must be r, w or c")),
+                };
+                let start = address(2)?;
+                let count = if words.len() > 3 { address(3)? } else { 1 };
+                let id = self.intcode.watch(start..start + count, kind);
+                format!("watchpoint {}", id)
+            }
+            "uw" => {
+                self.intcode.unwatch(address(1)?);
+                String::new()
+            }
+            "rec" if self.intcode.history.is_some() => {
+                self.intcode.stop_history();
+                "stopped recording history".to_owned()
+            }
+            "rec" => {
+                self.intcode.start_history();
+                "recording history".to_owned()
+            }
+            "rs" | "rc" if self.intcode.history.is_none() => {
+                return Err(anyhow!("no history is being recorded (use `rec`)"))
+            }
+            "rs" => {
+                let count = if words.len() > 1 { address(1)? } else { 1 };
+                let undone = (0..count).take_while(|_| self.intcode.step_back()).count();
+                format!("undid {} instructions", undone)
+            }
+            "rc" => {
+                let target = address(1)?;
+                if !self.intcode.reverse_to_write(target) {
+                    return Err(anyhow!("no recorded write to {}", target));
+                }
+                String::new()
+            }
+            "x" => {
+                let count = if words.len() > 2 { address(2)? } else { 1 };
+                self.memory(address(1)?, count)
+            }
+            "w" => {
+                let (address, value) = (address(1)?, argument(2)?);
+                self.intcode.program.write(address, value);
+                self.memory(address, 1)
+            }
+            "pc" => {
+                self.intcode.pointer = address(1)?;
+                self.intcode.halted = false;
+                String::new()
+            }
+            "rb" => {
+                self.intcode.relative_base = argument(1)?;
+                String::new()
+            }
+            "in" => {
+                for index in 1..words.len() {
+                    self.intcode.push_input(argument(index)?);
+                }
+                self.registers()
+            }
+            "r" | "regs" => self.registers(),
+            "h" | "help" => HELP.to_owned(),
+            "q" | "quit" => return Ok(None),
+            other => return Err(anyhow!("unknown command {} (try `help`)", other)),
+        };
+        Ok(Some(text))
+    }
+
+    /// Read commands from `input` until it ends or the user quits.
+    /// Errors in a command are reported and don't end the session.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> Result<(), Error> {
+        writeln!(output, "{}", self.current())?;
+        for line in input.lines() {
+            match self.execute(&line?) {
+                Ok(None) => break,
+                Ok(Some(text)) => {
+                    if !text.is_empty() {
+                        writeln!(output, "{}", text)?;
+                    }
+                }
+                Err(error) => writeln!(output, "error: {}", error)?,
+            }
+            writeln!(output, "{}", self.current())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::intcode_compiler::assembler::assemble;
+    use crate::intcode_compiler::countdown;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = countdown()?;
+        let mut debugger = Debugger::with_history(IntCode::new(program));
+        assert_eq!(debugger.resume()?, Stop::NeedsInput);
+        debugger.intcode.push_input(3);
+        debugger.toggle_opcode_breakpoint(OpCode::Output);
+        assert_eq!(debugger.resume()?, Stop::Breakpoint);
+        assert_eq!(debugger.intcode.pointer, 2);
+        assert_eq!(debugger.resume()?, Stop::Breakpoint);
+        assert_eq!(debugger.intcode.outputs, vec![3]);
+        debugger.toggle_opcode_breakpoint(OpCode::Output);
+        debugger.toggle_address_breakpoint(11);
+        assert_eq!(debugger.resume()?, Stop::Breakpoint);
+        assert_eq!(debugger.intcode.outputs, vec![3, 2, 1]);
+        assert_eq!(debugger.step()?, Stop::Halted);
+
+        debugger.execute("rc 12")?;
+        assert_eq!(debugger.intcode.pointer, 4);
+        assert_eq!(debugger.intcode.program[12], 1);
+        assert_eq!(
+            debugger.execute("rs 2")?,
+            Some("undid 2 instructions".to_owned())
+        );
+        assert_eq!(debugger.intcode.outputs, vec![3, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_2() -> Result<(), Error> {
+        let program = assemble("OUT [5]\nOUT [rb+5]\nHLT\n.data 7")?;
+        let mut debugger = Debugger::new(IntCode::new(program));
+        let mut output = Vec::new();
+        debugger.repl(
+            Cursor::new("w 5 8\nrb -1\ns\nx 4 2\nbogus\nrs\nrec\nc\nrs 2\nq\ns\n"),
+            &mut output,
+        )?;
+        let output = String::from_utf8(output)?;
+        assert_eq!(
+            output
+                .lines()
+                .map(str::trim_end)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            "0000: OUT [5]                          rb=0
+0005: 8
+0000: OUT [5]                          rb=0
+0000: OUT [5]                          rb=-1
+output: 8
+0002: OUT [rb+5]                       rb=-1
+0004: 99
+0005: 8
+0002: OUT [rb+5]                       rb=-1
+error: unknown command bogus (try `help`)
+0002: OUT [rb+5]                       rb=-1
+error: no history is being recorded (use `rec`)
+0002: OUT [rb+5]                       rb=-1
+recording history
+0002: OUT [rb+5]                       rb=-1
+output: 99
+halted
+0004: HLT                              rb=-1
+undid 2 instructions
+0002: OUT [rb+5]                       rb=-1"
+        );
+        Ok(())
+    }
+}
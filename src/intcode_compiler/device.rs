@@ -0,0 +1,261 @@
+//! Devices an IntCode machine reads its input from and writes its output to
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender};
+
+use anyhow::Error;
+
+use crate::intcode_compiler::loader::parse_program;
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::{IntCode, RunState};
+use crate::utility::{input_from_file, write_to_file};
+
+pub trait InputSource {
+    /// The next value to read, or None if there is nothing to read yet
+    fn next_input(&mut self) -> Result<Option<i64>, Error>;
+}
+
+pub trait OutputSink {
+    fn send(&mut self, value: i64) -> Result<(), Error>;
+}
+
+impl<F: FnMut() -> Option<i64>> InputSource for F {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        Ok(self())
+    }
+}
+
+impl<F: FnMut(i64)> OutputSink for F {
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        self(value);
+        Ok(())
+    }
+}
+
+/// Waits for the next value. A closed channel has nothing more to read.
+impl InputSource for Receiver<i64> {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        Ok(self.recv().ok())
+    }
+}
+
+impl OutputSink for Sender<i64> {
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        Sender::send(self, value).map_err(|_| anyhow!("output channel closed"))
+    }
+}
+
+impl OutputSink for Vec<i64> {
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+/// Reads from another machine, running it until it produces an output
+impl<M: Memory> InputSource for IntCode<M> {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        match self.run()? {
+            RunState::Output(value) => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Queues values as input for another machine
+impl<M: Memory> OutputSink for IntCode<M> {
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        self.push_input(value);
+        Ok(())
+    }
+}
+
+/// One integer per line typed on stdin
+pub struct Stdin {
+    prompt: String,
+}
+
+impl Stdin {
+    pub fn new(prompt: &str) -> Self {
+        Stdin {
+            prompt: prompt.to_owned(),
+        }
+    }
+}
+
+impl InputSource for Stdin {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        let stdin = io::stdin();
+        loop {
+            print!("{}", self.prompt);
+            io::stdout().flush()?;
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            match line.trim().parse() {
+                Ok(value) => return Ok(Some(value)),
+                Err(_) => println!("not an integer: {}", line.trim()),
+            }
+        }
+    }
+}
+
+/// Replays values saved from an earlier run, then reads from `source`.
+/// Everything read is kept so it can be saved for the next run.
+pub struct Recorded<S: InputSource> {
+    replay: VecDeque<i64>,
+    source: S,
+    pub history: Vec<i64>,
+}
+
+impl<S: InputSource> Recorded<S> {
+    pub fn new(source: S) -> Self {
+        Recorded {
+            replay: VecDeque::new(),
+            source,
+            history: Vec::new(),
+        }
+    }
+
+    /// Replay the comma-separated values in `filename` first
+    pub fn load(filename: &str, source: S) -> Result<Self, Error> {
+        let mut recorded = Recorded::new(source);
+        recorded.replay = parse_program(&input_from_file(filename)?)?.into();
+        Ok(recorded)
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), Error> {
+        let values: Vec<_> = self.history.iter().map(|i| i.to_string()).collect();
+        write_to_file(filename, &values.join(","))
+    }
+}
+
+impl<S: InputSource> InputSource for Recorded<S> {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        let value = match self.replay.pop_front() {
+            Some(value) => Some(value),
+            None => self.source.next_input()?,
+        };
+        self.history.extend(value);
+        Ok(value)
+    }
+}
+
+/// Lets a separate source and sink act as one device
+struct Connection<'a, I, O> {
+    input: &'a mut I,
+    output: &'a mut O,
+}
+
+impl<'a, I: InputSource, O> InputSource for Connection<'a, I, O> {
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        self.input.next_input()
+    }
+}
+
+impl<'a, I, O: OutputSink> OutputSink for Connection<'a, I, O> {
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        self.output.send(value)
+    }
+}
+
+impl<M: Memory> IntCode<M> {
+    /// Run, reading from `input` whenever the input queue is empty
+    /// and sending every output to `output`.
+    /// Stops when `input` has nothing to read (returning NeedsInput),
+    /// or for any reason `run` would stop other than an output.
+    pub fn run_with<I: InputSource, O: OutputSink>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<RunState, Error> {
+        self.run_device(&mut Connection { input, output })
+    }
+
+    /// `run_with` for a device that is both the input and the output, such as a robot
+    pub fn run_device<D: InputSource + OutputSink>(
+        &mut self,
+        device: &mut D,
+    ) -> Result<RunState, Error> {
+        loop {
+            match self.run()? {
+                RunState::NeedsInput => match device.next_input()? {
+                    Some(value) => self.push_input(value),
+                    None => return Ok(RunState::NeedsInput),
+                },
+                RunState::Output(value) => device.send(value)?,
+                state => return Ok(state),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use crate::intcode_compiler::assembler::assemble;
+    use crate::intcode_compiler::memory::PagedMemory;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = assemble(
+            "loop:   IN -> [x]
+                    MUL [x], #2 -> [x]
+                    OUT [x]
+                    JT #1, #loop
+            x:      .data 0",
+        )?;
+        let mut inputs = vec![1, 2, 3].into_iter();
+        let mut outputs = Vec::new();
+        let mut intcode = IntCode::new(program.clone());
+        assert_eq!(
+            intcode.run_with(&mut || inputs.next(), &mut outputs)?,
+            RunState::NeedsInput
+        );
+        assert_eq!(outputs, vec![2, 4, 6]);
+
+        let (input_sender, input_receiver) = channel();
+        let (output_sender, output_receiver) = channel();
+        for value in &[5, 0] {
+            input_sender.send(*value)?;
+        }
+        drop(input_sender);
+        let mut intcode = IntCode::new(program.clone());
+        let (mut input, mut output) = (input_receiver, output_sender);
+        assert_eq!(
+            intcode.run_with(&mut input, &mut output)?,
+            RunState::NeedsInput
+        );
+        assert_eq!(output_receiver.try_iter().collect::<Vec<_>>(), vec![10, 0]);
+
+        // a doubler fed by another doubler, on sparse memory
+        let mut first = IntCode::new(PagedMemory::new(&program));
+        first.extend_inputs(vec![1, 7]);
+        let mut second = IntCode::new(PagedMemory::new(&program));
+        let mut outputs = Vec::new();
+        assert_eq!(
+            second.run_with(&mut first, &mut outputs)?,
+            RunState::NeedsInput
+        );
+        assert_eq!(outputs, vec![4, 28]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_2() -> Result<(), Error> {
+        let mut keys = vec![3, 4].into_iter();
+        let mut recorded = Recorded::new(|| keys.next());
+        recorded.replay.extend(vec![1, 2]);
+        let mut read = Vec::new();
+        while let Some(value) = recorded.next_input()? {
+            read.push(value);
+        }
+        assert_eq!(read, vec![1, 2, 3, 4]);
+        assert_eq!(recorded.history, read);
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+
+use crate::intcode_compiler::{Instruction, Mode, OpCode, Parameter};
+
+impl OpCode {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            OpCode::Add => "ADD",
+            OpCode::Multiply => "MUL",
+            OpCode::Input => "IN",
+            OpCode::Output => "OUT",
+            OpCode::JumpIfTrue => "JT",
+            OpCode::JumpIfFalse => "JF",
+            OpCode::LessThan => "LT",
+            OpCode::Equals => "EQ",
+            OpCode::RelativeBaseOffset => "ARB",
+            OpCode::Halt => "HLT",
+        }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative => write!(f, "[rb{:+}]", self.value),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// e.g. `ADD [12], #3 -> [rb+4]`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+        let parameters = self.parameters();
+        let (reads, write) = if self.opcode.writes() {
+            (&parameters[..parameters.len() - 1], parameters.last())
+        } else {
+            (parameters, None)
+        };
+        for (i, parameter) in reads.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, parameter)?;
+        }
+        if let Some(parameter) = write {
+            write!(f, " -> {}", parameter)?;
+        }
+        Ok(())
+    }
+}
+
+/// Turn a program into a listing with one instruction per line.
+/// Words that don't decode to an instruction (or whose parameters run past the end) are shown as DATA.
+pub fn disassemble(program: &[i64]) -> String {
+    let mut lines = Vec::new();
+    let mut pointer = 0;
+    while pointer < program.len() {
+        let read = |address: usize| program.get(address).copied().unwrap_or(0);
+        match Instruction::decode_with(read, pointer, 0) {
+            Ok(instruction) if pointer + instruction.opcode.num_parameters() < program.len() => {
+                lines.push(format!("{:04}: {}", pointer, instruction));
+                pointer += instruction.opcode.num_parameters() + 1;
+            }
+            _ => {
+                lines.push(format!("{:04}: DATA {}", pointer, program[pointer]));
+                pointer += 1;
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1() {
+        let program = vec![21001, 12, 3, 4, 3, 7, 1005, 7, 0, 204, -1, 99, 123, 1];
+        assert_eq!(
+            disassemble(&program),
+            "0000: ADD [12], #3 -> [rb+4]
+0004: IN -> [7]
+0006: JT [7], #0
+0009: OUT [rb-1]
+0011: HLT
+0012: DATA 123
+0013: DATA 1"
+        );
+    }
+}
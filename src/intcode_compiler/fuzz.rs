@@ -0,0 +1,272 @@
+//! Random programs for testing interpreters against IntCode and for checking that
+//! IntCode survives garbage without panicking
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::intcode_compiler::memory::PagedMemory;
+use crate::intcode_compiler::{IntCode, RunState};
+
+/// Instructions run before a program is given up on
+const BUDGET: u64 = 10_000;
+
+/// How a run ended: the final memory, or why it failed
+pub type Outcome = Result<Vec<i64>, String>;
+
+/// xorshift64*, so runs are reproducible from a seed without pulling in a crate
+#[derive(Copy, Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in 0..n
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Mostly small values, sometimes negative or at the extremes
+    fn value(&mut self) -> i64 {
+        match self.below(8) {
+            0 => [i64::MIN, i64::MAX, self.next_u64() as i64][self.below(3) as usize],
+            1 => -(self.below(100) as i64),
+            _ => self.below(100) as i64,
+        }
+    }
+}
+
+/// A day 2 style program: `instructions` adds and multiplies four cells apart,
+/// reading and writing only addresses inside the program, then a halt
+pub fn arithmetic_program(rng: &mut Rng, instructions: usize) -> Vec<i64> {
+    let len = instructions as u64 * 4 + 1;
+    let mut program: Vec<_> = (0..instructions)
+        .flat_map(|_| {
+            let opcode = 1 + rng.below(2) as i64;
+            vec![
+                opcode,
+                rng.below(len) as i64,
+                rng.below(len) as i64,
+                rng.below(len) as i64,
+            ]
+        })
+        .collect();
+    program.push(99);
+    program
+}
+
+/// Whether every instruction before the first halt, read four cells apart as day 2 does,
+/// is an add or multiply with addresses inside the program
+pub fn is_arithmetic(program: &[i64]) -> bool {
+    let in_range = |&address: &i64| address >= 0 && (address as usize) < program.len();
+    for instruction in program.chunks(4) {
+        match instruction[0] {
+            99 => return true,
+            1 | 2 if instruction.len() == 4 && instruction[1..].iter().all(in_range) => (),
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Keeps the panics `catch` expects out of the test output.
+/// Only built for tests, as it puts a hook in front of the process-wide panic hook.
+#[cfg(test)]
+mod quiet {
+    use std::cell::Cell;
+    use std::panic;
+    use std::sync::Once;
+
+    thread_local! {
+        static QUIET: Cell<bool> = const { Cell::new(false) };
+    }
+
+    static HOOK: Once = Once::new();
+
+    /// Panics on this thread aren't printed while one of these is alive
+    pub struct Quiet(bool);
+
+    impl Quiet {
+        pub fn new() -> Self {
+            HOOK.call_once(|| {
+                let hook = panic::take_hook();
+                panic::set_hook(Box::new(move |info| {
+                    if !QUIET.with(Cell::get) {
+                        hook(info)
+                    }
+                }));
+            });
+            Quiet(QUIET.with(|quiet| quiet.replace(true)))
+        }
+    }
+
+    impl Drop for Quiet {
+        fn drop(&mut self) {
+            QUIET.with(|quiet| quiet.set(self.0));
+        }
+    }
+}
+
+/// Run `f`, turning a panic into an error
+pub fn catch<F: FnOnce() -> Outcome>(f: F) -> Outcome {
+    #[cfg(test)]
+    let _quiet = quiet::Quiet::new();
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        Err(match panic.downcast_ref::<&str>() {
+            Some(message) => format!("panic: {}", message),
+            None => match panic.downcast_ref::<String>() {
+                Some(message) => format!("panic: {}", message),
+                None => "panic".to_owned(),
+            },
+        })
+    })
+}
+
+/// Run `program` on IntCode with no input until it halts
+pub fn run_intcode(program: &[i64]) -> Outcome {
+    catch(|| {
+        let mut intcode = IntCode::new(program.to_vec());
+        intcode.set_budget(Some(BUDGET));
+        loop {
+            match intcode.run().map_err(|e| e.to_string())? {
+                RunState::Halted => return Ok(intcode.program),
+                RunState::Output(_) | RunState::Watchpoint(_) => (),
+                state => return Err(format!("stopped with {:?}", state)),
+            }
+        }
+    })
+}
+
+/// Both runs halted with different memory, or only one of them failed
+pub fn differs(a: &Outcome, b: &Outcome) -> bool {
+    match (a, b) {
+        (Ok(a), Ok(b)) => a != b,
+        (Err(_), Err(_)) => false,
+        _ => true,
+    }
+}
+
+/// Shrink `program` while `failing` still holds: first drop whole instructions,
+/// then bring cells down towards zero
+pub fn minimise<F: Fn(&[i64]) -> bool>(program: &[i64], failing: F) -> Vec<i64> {
+    let mut program = program.to_vec();
+    let mut start = 0;
+    while start < program.len() {
+        let mut candidate = program.clone();
+        candidate.drain(start..(start + 4).min(program.len()));
+        if failing(&candidate) {
+            program = candidate;
+        } else {
+            start += 4;
+        }
+    }
+    for address in 0..program.len() {
+        while program[address] != 0 {
+            let mut candidate = program.clone();
+            candidate[address] /= 2;
+            if failing(&candidate) {
+                program = candidate;
+            } else {
+                break;
+            }
+        }
+    }
+    program
+}
+
+/// Run `runs` random arithmetic programs on both `reference` and IntCode.
+/// Returns the smallest program, after minimising, on which their outcomes differ.
+pub fn differential<F: Fn(&[i64]) -> Outcome>(
+    seed: u64,
+    runs: usize,
+    reference: F,
+) -> Option<Vec<i64>> {
+    let mut rng = Rng::new(seed);
+    let failing = |program: &[i64]| {
+        is_arithmetic(program) && differs(&catch(|| reference(program)), &run_intcode(program))
+    };
+    (0..runs)
+        .map(|_| {
+            let instructions = 1 + rng.below(8) as usize;
+            arithmetic_program(&mut rng, instructions)
+        })
+        .filter(|program| failing(program))
+        .map(|program| minimise(&program, failing))
+        .min_by_key(|program| (program.len(), program.iter().map(|&i| i.abs()).sum::<i64>()))
+}
+
+/// A program of random cells, biased towards real opcodes with random parameter modes
+fn garbage_program(rng: &mut Rng, len: usize) -> Vec<i64> {
+    (0..len)
+        .map(|_| match rng.below(3) {
+            0 => {
+                let opcode = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99][rng.below(10) as usize];
+                let modes = (0..3).fold(0, |modes, _| modes * 10 + rng.below(4) as i64);
+                modes * 100 + opcode
+            }
+            _ => rng.value(),
+        })
+        .collect()
+}
+
+/// Run `program` on sparse memory, so wild writes stay cheap, until it stops for any reason
+fn run_garbage(program: &[i64], inputs: &[i64]) -> Outcome {
+    catch(|| {
+        let mut intcode = IntCode::new(PagedMemory::new(program));
+        intcode.set_budget(Some(BUDGET));
+        intcode.extend_inputs(inputs.iter().copied());
+        while let Ok(RunState::Output(_)) = intcode.run() {}
+        Ok(Vec::new())
+    })
+}
+
+/// Run `runs` random programs with random inputs on IntCode.
+/// Returns the first program that made it panic, minimised, with the panic message.
+pub fn find_panic(seed: u64, runs: usize) -> Option<(Vec<i64>, String)> {
+    let mut rng = Rng::new(seed);
+    for _ in 0..runs {
+        let len = 1 + rng.below(32) as usize;
+        let program = garbage_program(&mut rng, len);
+        let inputs: Vec<_> = (0..4).map(|_| rng.value()).collect();
+        if let Err(message) = run_garbage(&program, &inputs) {
+            let program = minimise(&program, |program| {
+                run_garbage(program, &inputs) == Err(message.clone())
+            });
+            return Some((program, message));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1() {
+        // multiplies as if it were adding
+        let reference = |program: &[i64]| -> Outcome {
+            let mut memory = program.to_vec();
+            for i in (0..memory.len()).step_by(4) {
+                if memory[i] == 99 {
+                    break;
+                }
+                let (a, b) = (memory[i + 1] as usize, memory[i + 2] as usize);
+                let target = memory[i + 3] as usize;
+                memory[target] = memory[a].wrapping_add(memory[b]);
+            }
+            Ok(memory)
+        };
+        assert_eq!(differential(1, 200, reference), Some(vec![2, 0, 1, 0, 99]));
+        assert_eq!(differential(1, 200, run_intcode), None);
+        for seed in 1..=5 {
+            assert_eq!(find_panic(seed, 500), None);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::{Instruction, IntCode, OpCode};
+
+/// Remembers the (pointer, relative base) pairs visited since memory last changed, I/O happened
+/// or `run` was called, as anything may have been changed from outside in between.
+/// Revisiting one means the whole machine state has repeated, so the program will loop forever.
+/// Loops that keep changing memory (e.g. a counter that wraps around) are not caught.
+#[derive(Clone, Debug, Default)]
+pub(super) struct LoopGuard {
+    seen: HashSet<(usize, i64)>,
+}
+
+/// Address written by an instruction and the value it held before
+pub(super) type PendingWrite = Option<(usize, i64)>;
+
+impl<M: Memory> IntCode<M> {
+    /// Stop `run` with OutOfBudget after this many more instructions. `None` removes the limit.
+    /// Setting a new budget lets a stopped machine carry on where it left off.
+    pub fn set_budget(&mut self, budget: Option<u64>) {
+        self.budget = budget;
+    }
+
+    /// Allow this many more instructions on top of what is left of the budget
+    pub fn raise_budget(&mut self, instructions: u64) {
+        if let Some(budget) = self.budget.as_mut() {
+            *budget += instructions;
+        }
+    }
+
+    pub fn remaining_budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    /// Stop `run` with InfiniteLoop when the machine state repeats without any I/O in between
+    pub fn detect_loops(&mut self, enabled: bool) {
+        self.loop_guard = if enabled {
+            Some(LoopGuard::default())
+        } else {
+            None
+        };
+    }
+
+    pub(super) fn reset_loop_guard(&mut self) {
+        if let Some(guard) = self.loop_guard.as_mut() {
+            guard.seen.clear();
+        }
+    }
+
+    pub(super) fn in_loop(&self) -> bool {
+        match &self.loop_guard {
+            Some(guard) => guard.seen.contains(&(self.pointer, self.relative_base)),
+            None => false,
+        }
+    }
+
+    pub(super) fn guard_before(&mut self, instruction: &Instruction) -> PendingWrite {
+        let state = (self.pointer, self.relative_base);
+        self.loop_guard.as_mut()?.seen.insert(state);
+        let parameters = instruction.parameters();
+        if instruction.opcode.writes() {
+            let address = parameters[parameters.len() - 1].address(self).ok()?;
+            Some((address, self.program.read(address)))
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn guard_after(&mut self, instruction: &Instruction, write: PendingWrite) {
+        let changed = match write {
+            Some((address, before)) => self.program.read(address) != before,
+            None => false,
+        };
+        let io = instruction.opcode == OpCode::Input || instruction.opcode == OpCode::Output;
+        if let Some(guard) = self.loop_guard.as_mut() {
+            if changed || io {
+                guard.seen.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::intcode_compiler::assembler::assemble;
+    use crate::intcode_compiler::{IntcodeError, RunState};
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), IntcodeError> {
+        let program = vec![1101, 2, 3, 7, 4, 7, 99, 0];
+        let mut intcode = IntCode::new(program);
+        intcode.set_budget(Some(1));
+        assert_eq!(intcode.run()?, RunState::OutOfBudget);
+        assert_eq!(intcode.remaining_budget(), Some(0));
+        assert_eq!(intcode.run()?, RunState::OutOfBudget);
+        intcode.raise_budget(1);
+        assert_eq!(intcode.run()?, RunState::Output(5));
+        intcode.set_budget(None);
+        assert_eq!(intcode.run()?, RunState::Halted);
+
+        let mut intcode = IntCode::new(vec![1105, 1, 0]);
+        intcode.set_budget(Some(100));
+        match intcode.process() {
+            Err(IntcodeError::OutOfBudget { context }) => assert_eq!(context.pointer, 0),
+            other => panic!("expected OutOfBudget, found {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_2() -> Result<(), Error> {
+        // prints its input once, then spins without changing anything
+        let program = assemble(
+            "       IN -> [x]
+                    OUT [x]
+            spin:   ADD [x], #0 -> [x]
+                    JT #1, #spin
+            x:      .data 0",
+        )?;
+        let mut intcode = IntCode::new(program);
+        intcode.detect_loops(true);
+        intcode.push_input(4);
+        assert_eq!(intcode.run()?, RunState::Output(4));
+        assert_eq!(intcode.run()?, RunState::InfiniteLoop);
+        assert_eq!(intcode.pointer, 4);
+
+        // waits for the flag at 7 to be set from outside
+        let mut intcode = IntCode::new(vec![1006, 7, 0, 99, 0, 0, 0, 0]);
+        intcode.detect_loops(true);
+        assert_eq!(intcode.run()?, RunState::InfiniteLoop);
+        intcode.program[7] = 1;
+        assert_eq!(intcode.run()?, RunState::Halted);
+
+        // a counting loop changes memory every time round, so it is not flagged
+        let program = assemble(
+            "loop:   ADD [x], #1 -> [x]
+                    EQ [x], #1000 -> [done]
+                    JF [done], #loop
+                    HLT
+            x:      .data 0
+            done:   .data 0",
+        )?;
+        let mut intcode = IntCode::new(program);
+        intcode.detect_loops(true);
+        assert_eq!(intcode.run()?, RunState::Halted);
+        assert_eq!(intcode.run()?, RunState::Halted);
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! Undo log of executed instructions, so a machine can be stepped backwards
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::{Instruction, IntCode, OpCode};
+
+/// What one instruction changed, with the values it overwrote
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Undo {
+    pointer: usize,
+    relative_base: i64,
+    halted: bool,
+    /// Address written and the value it held before
+    write: Option<(usize, i64)>,
+    /// Input consumed
+    input: Option<i64>,
+    output: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct History {
+    undos: Vec<Undo>,
+}
+
+impl<M: Memory> IntCode<M> {
+    /// Record every instruction executed from now on so it can be undone.
+    /// Changes made from outside, such as pushing input or writing to `program`,
+    /// are not recorded.
+    pub fn start_history(&mut self) {
+        self.history = Some(History::default());
+    }
+
+    pub fn stop_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Number of instructions that can be undone
+    pub fn history_len(&self) -> usize {
+        self.history
+            .as_ref()
+            .map_or(0, |history| history.undos.len())
+    }
+
+    pub(super) fn history_before(&self, instruction: &Instruction) -> Option<Undo> {
+        self.history.as_ref()?;
+        let parameters = instruction.parameters();
+        let write = if instruction.opcode.writes() {
+            let address = parameters[parameters.len() - 1].address(self).ok()?;
+            Some((address, self.program.read(address)))
+        } else {
+            None
+        };
+        Some(Undo {
+            pointer: self.pointer,
+            relative_base: self.relative_base,
+            halted: self.halted,
+            write,
+            input: match instruction.opcode {
+                OpCode::Input => self.inputs.front().copied(),
+                _ => None,
+            },
+            output: instruction.opcode == OpCode::Output,
+        })
+    }
+
+    pub(super) fn history_after(&mut self, undo: Option<Undo>) {
+        if let (Some(history), Some(undo)) = (self.history.as_mut(), undo) {
+            history.undos.push(undo);
+        }
+    }
+
+    /// Undo the last instruction executed. Returns false if there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self
+            .history
+            .as_mut()
+            .and_then(|history| history.undos.pop())
+        {
+            Some(undo) => undo,
+            None => return false,
+        };
+        self.pointer = undo.pointer;
+        self.relative_base = undo.relative_base;
+        self.halted = undo.halted;
+        if let Some((address, value)) = undo.write {
+            self.program.write(address, value);
+        }
+        if let Some(value) = undo.input {
+            self.inputs.push_front(value);
+        }
+        if undo.output {
+            self.outputs.pop();
+        }
+        true
+    }
+
+    /// Step back to just before the last recorded instruction that wrote to `address`.
+    /// Returns false, leaving the machine as it was, if no recorded instruction wrote there.
+    pub fn reverse_to_write(&mut self, address: usize) -> bool {
+        let wrote = |undo: &Undo| matches!(undo.write, Some((written, _)) if written == address);
+        let steps = match &self.history {
+            Some(history) => match history.undos.iter().rev().position(wrote) {
+                Some(position) => position + 1,
+                None => return false,
+            },
+            None => return false,
+        };
+        for _ in 0..steps {
+            self.step_back();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::intcode_compiler::countdown;
+    use crate::intcode_compiler::RunState;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = countdown()?;
+        let mut intcode = IntCode::new(program.clone());
+        intcode.start_history();
+        intcode.push_input(3);
+        assert_eq!(intcode.process()?, Some(1));
+        assert_eq!(intcode.history_len(), 11);
+
+        // back to just before x went from 1 to 0
+        assert!(intcode.reverse_to_write(12));
+        assert_eq!(intcode.pointer, 4);
+        assert_eq!(intcode.program[12], 1);
+        assert_eq!(intcode.outputs, vec![3, 2, 1]);
+        assert!(!intcode.halted);
+        assert!(!intcode.reverse_to_write(100));
+
+        while intcode.step_back() {}
+        assert_eq!(intcode.program, program);
+        assert_eq!(intcode.pointer, 0);
+        assert!(intcode.outputs.is_empty());
+        assert_eq!(intcode.run()?, RunState::Output(3));
+
+        let mut intcode = IntCode::new(vec![109, 5, 99]);
+        intcode.start_history();
+        intcode.process()?;
+        assert!(intcode.step_back() && intcode.step_back());
+        assert_eq!((intcode.pointer, intcode.relative_base), (0, 0));
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! Reading Intcode programs from text.
+//! Values are separated by commas and/or whitespace, so programs can be split over lines,
+//! and `;` or `#` starts a comment running to the end of the line.
+//! Two commas with nothing between them are an error rather than a skipped value.
+use std::fmt;
+use std::io::{self, Read};
+
+use anyhow::Error;
+
+use crate::utility::input_from_file;
+
+/// A token that isn't an integer
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    /// Position in the program the value would have had
+    pub index: usize,
+    /// Line of the text it is on, counting from 1
+    pub line: usize,
+    pub token: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bad value {:?} at index {} (line {})",
+            self.token, self.index, self.line
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse_program(text: &str) -> Result<Vec<i64>, ParseError> {
+    let mut program = Vec::new();
+    // no value since the last comma, or the start, so another comma would leave one out
+    let mut empty = true;
+    for (line_number, line) in text.lines().enumerate() {
+        let code = line.split(&[';', '#'][..]).next().unwrap_or("");
+        let error = |index, token: &str| ParseError {
+            index,
+            line: line_number + 1,
+            token: token.to_owned(),
+        };
+        for (i, field) in code.split(',').enumerate() {
+            if i > 0 {
+                if empty {
+                    return Err(error(program.len(), ""));
+                }
+                empty = true;
+            }
+            for token in field.split_whitespace() {
+                let value = token.parse().map_err(|_| error(program.len(), token))?;
+                program.push(value);
+                empty = false;
+            }
+        }
+    }
+    Ok(program)
+}
+
+pub fn read_program<R: Read>(mut reader: R) -> Result<Vec<i64>, Error> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    Ok(parse_program(&text)?)
+}
+
+pub fn program_from_file(filename: &str) -> Result<Vec<i64>, Error> {
+    Ok(parse_program(&input_from_file(filename)?)?)
+}
+
+pub fn program_from_stdin() -> Result<Vec<i64>, Error> {
+    read_program(io::stdin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let text = "  3,9, 8,9,10,9 ; is it 8?
+            4,9,   # say so
+            99,-1,8\n";
+        assert_eq!(
+            parse_program(text)?,
+            vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]
+        );
+        assert_eq!(read_program(text.as_bytes())?, parse_program(text)?);
+        assert!(parse_program("")?.is_empty());
+
+        let error = parse_program("1,0,0,0\n99,1O,3").err().unwrap();
+        assert_eq!(
+            error,
+            ParseError {
+                index: 5,
+                line: 2,
+                token: "1O".to_owned()
+            }
+        );
+        assert_eq!(error.to_string(), "bad value \"1O\" at index 5 (line 2)");
+
+        assert_eq!(parse_program("1 2,\n3,4,")?, vec![1, 2, 3, 4]);
+        assert!(parse_program("1,,2").is_err());
+        assert!(parse_program(",1").is_err());
+        let error = parse_program("1,\n,\n2").err().unwrap();
+        assert_eq!((error.index, error.line), (1, 2));
+        let error = parse_program("1,\n2, ,3").err().unwrap();
+        assert_eq!(error.to_string(), "bad value \"\" at index 2 (line 2)");
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+/// Storage for an IntCode machine's cells. Unwritten cells read as 0.
+pub trait Memory {
+    fn read(&self, address: usize) -> i64;
+    fn write(&mut self, address: usize, value: i64);
+    /// Every non-zero cell, in address order
+    fn cells(&self) -> Vec<(usize, i64)>;
+    /// One past the highest address with storage behind it
+    fn size(&self) -> usize;
+}
+
+/// Dense memory, grown to cover the highest address written. The fast default.
+impl Memory for Vec<i64> {
+    #[inline]
+    fn read(&self, address: usize) -> i64 {
+        self.get(address).copied().unwrap_or(0)
+    }
+
+    #[inline]
+    fn write(&mut self, address: usize, value: i64) {
+        if address >= self.len() {
+            self.resize(address + 1, 0);
+        }
+        self[address] = value;
+    }
+
+    fn cells(&self) -> Vec<(usize, i64)> {
+        self.iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, value)| value != 0)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+const PAGE_SIZE: usize = 1024;
+
+/// Sparse memory allocated in fixed-size pages on first write,
+/// so programs scattering writes across a wide address space stay small
+#[derive(Clone, Debug, Default)]
+pub struct PagedMemory {
+    pages: HashMap<usize, Box<[i64]>>,
+}
+
+impl PagedMemory {
+    pub fn new(program: &[i64]) -> Self {
+        let mut memory = PagedMemory::default();
+        for (address, &value) in program.iter().enumerate() {
+            memory.write(address, value);
+        }
+        memory
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl Memory for PagedMemory {
+    fn read(&self, address: usize) -> i64 {
+        self.pages
+            .get(&(address / PAGE_SIZE))
+            .map_or(0, |page| page[address % PAGE_SIZE])
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        let page = address / PAGE_SIZE;
+        if value == 0 && !self.pages.contains_key(&page) {
+            return;
+        }
+        self.pages
+            .entry(page)
+            .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice())[address % PAGE_SIZE] = value;
+    }
+
+    fn cells(&self) -> Vec<(usize, i64)> {
+        let mut pages: Vec<_> = self.pages.iter().collect();
+        pages.sort_unstable_by_key(|&(page, _)| *page);
+        pages
+            .into_iter()
+            .flat_map(|(page, cells)| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &value)| value != 0)
+                    .map(move |(offset, &value)| (page * PAGE_SIZE + offset, value))
+            })
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        self.pages
+            .keys()
+            .max()
+            .map_or(0, |page| (page + 1) * PAGE_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::intcode_compiler::IntCode;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let far = 1 << 40;
+        let program = vec![1101, 5, 6, far, 21101, 0, 0, 2 * far, 4, far, 99];
+        let mut intcode = IntCode::new(PagedMemory::new(&program));
+        assert_eq!(intcode.process()?, Some(11));
+        assert_eq!(intcode.program.num_pages(), 2);
+        assert_eq!(intcode.program.read(far as usize), 11);
+        assert_eq!(intcode.program.read(far as usize + 1), 0);
+        let cells = intcode.program.cells();
+        assert_eq!(cells[0], (0, 1101));
+        assert_eq!(
+            cells[cells.len() - 2..],
+            [(program.len() - 1, 99), (far as usize, 11)]
+        );
+
+        let mut memory = Vec::new();
+        memory.write(3, 7);
+        assert_eq!(memory, vec![0, 0, 0, 7]);
+        assert_eq!(memory.read(10), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Many IntCode machines exchanging packets, as in the day 23 puzzle.
+//! A machine sends a packet by outputting its destination address followed by X and Y,
+//! and reads -1 whenever it asks for input while no packet is waiting.
+use anyhow::Error;
+
+use crate::intcode_compiler::{IntCode, RunState};
+
+/// Value read by a machine waiting on an empty queue
+const NO_PACKET: i64 = -1;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// Receives the packets sent to the network's special address
+pub trait Hook {
+    fn receive(&mut self, packet: Packet);
+
+    /// Called after a round in which every machine waited on an empty queue.
+    /// A returned packet is delivered to its destination machine.
+    fn idle(&mut self) -> Option<Packet>;
+}
+
+/// Keeps every packet it receives and never wakes the network
+#[derive(Clone, Debug, Default)]
+pub struct Log {
+    pub packets: Vec<Packet>,
+}
+
+impl Hook for Log {
+    fn receive(&mut self, packet: Packet) {
+        self.packets.push(packet);
+    }
+
+    fn idle(&mut self) -> Option<Packet> {
+        None
+    }
+}
+
+/// Remembers the last packet it received and sends it to machine 0 when the network goes idle
+#[derive(Clone, Debug, Default)]
+pub struct Nat {
+    pub last: Option<Packet>,
+    /// Y values sent to machine 0, in order
+    pub sent: Vec<i64>,
+}
+
+impl Hook for Nat {
+    fn receive(&mut self, packet: Packet) {
+        self.last = Some(packet);
+    }
+
+    fn idle(&mut self) -> Option<Packet> {
+        let packet = self.last?;
+        self.sent.push(packet.y);
+        Some(Packet {
+            destination: 0,
+            ..packet
+        })
+    }
+}
+
+pub struct Network<H: Hook> {
+    machines: Vec<IntCode>,
+    /// Output of each machine not yet making up a whole packet
+    partial: Vec<Vec<i64>>,
+    hook_address: i64,
+    pub hook: H,
+}
+
+impl<H: Hook> Network<H> {
+    /// `size` copies of `program`, each given its address as its first input
+    pub fn new(program: &[i64], size: usize, hook_address: i64, hook: H) -> Self {
+        let machines = (0..size)
+            .map(|address| {
+                let mut intcode = IntCode::new(program.to_vec());
+                intcode.push_input(address as i64);
+                intcode
+            })
+            .collect();
+        Network {
+            machines,
+            partial: vec![Vec::new(); size],
+            hook_address,
+            hook,
+        }
+    }
+
+    pub fn machine(&self, address: usize) -> &IntCode {
+        &self.machines[address]
+    }
+
+    /// Queue a packet for its destination
+    pub fn send(&mut self, packet: Packet) -> Result<(), Error> {
+        if packet.destination == self.hook_address {
+            self.hook.receive(packet);
+            return Ok(());
+        }
+        let machine = self
+            .machines
+            .get_mut(packet.destination as usize)
+            .filter(|_| packet.destination >= 0)
+            .ok_or_else(|| anyhow!("packet for unknown address {}", packet.destination))?;
+        machine.extend_inputs(vec![packet.x, packet.y]);
+        Ok(())
+    }
+
+    /// Run each machine in turn until it waits on an empty queue or halts,
+    /// delivering packets as they are sent.
+    /// If nothing was sent and nothing is waiting to be read, the hook is told the network is idle.
+    /// Returns the packets sent by the machines during the round.
+    pub fn round(&mut self) -> Result<Vec<Packet>, Error> {
+        let mut packets = Vec::new();
+        for address in 0..self.machines.len() {
+            let mut waited = false;
+            loop {
+                match self.machines[address].run()? {
+                    RunState::Output(value) => {
+                        let partial = &mut self.partial[address];
+                        partial.push(value);
+                        if partial.len() == 3 {
+                            let packet = Packet {
+                                destination: partial[0],
+                                x: partial[1],
+                                y: partial[2],
+                            };
+                            partial.clear();
+                            self.send(packet)?;
+                            packets.push(packet);
+                        }
+                    }
+                    RunState::NeedsInput if !waited => {
+                        self.machines[address].push_input(NO_PACKET);
+                        waited = true;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        let waiting = self
+            .machines
+            .iter()
+            .any(|machine| !machine.inputs.is_empty());
+        if packets.is_empty() && !waiting {
+            if let Some(packet) = self.hook.idle() {
+                self.send(packet)?;
+            }
+        }
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode_compiler::assembler::assemble;
+
+    use super::*;
+
+    /// Machine 0 starts a packet round the ring; the others pass it on with Y increased by one.
+    /// The last machine sends to 255.
+    fn ring() -> Result<Vec<i64>, Error> {
+        assemble(
+            "       IN -> [addr]
+                    ADD [addr], #1 -> [next]
+                    JT [addr], #loop
+                    OUT #1
+                    OUT #0
+                    OUT #0
+            loop:   IN -> [x]
+                    EQ [x], #-1 -> [t]
+                    JT [t], #loop
+                    IN -> [y]
+                    ADD [y], #1 -> [y]
+                    EQ [next], #3 -> [t]
+                    JF [t], #send
+                    ADD #255, #0 -> [next]
+            send:   OUT [next]
+                    OUT [x]
+                    OUT [y]
+                    JT #1, #loop
+            addr:   .data 0
+            next:   .data 0
+            x:      .data 0
+            y:      .data 0
+            t:      .data 0",
+        )
+    }
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let mut network = Network::new(&ring()?, 3, 255, Log::default());
+        let packets = network.round()?;
+        assert_eq!(packets.len(), 3);
+        assert_eq!(
+            network.hook.packets,
+            vec![Packet {
+                destination: 255,
+                x: 0,
+                y: 2
+            }]
+        );
+        assert!(network.round()?.is_empty());
+        assert_eq!(network.machine(1).outputs, vec![2, 0, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_2() -> Result<(), Error> {
+        let mut network = Network::new(&ring()?, 3, 255, Nat::default());
+        while network.hook.sent.len() < 3 {
+            network.round()?;
+        }
+        assert_eq!(network.hook.sent, vec![2, 5, 8]);
+        assert!(network
+            .send(Packet {
+                destination: 7,
+                x: 0,
+                y: 0
+            })
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::{Instruction, IntCode, Mode, OpCode};
+
+/// Number of entries shown in each per-address table of the report
+const REPORT_ROWS: usize = 10;
+
+/// Execution counts collected while profiling.
+/// Memory accesses are those made by parameters, not instruction fetches.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub instructions: u64,
+    pub opcodes: HashMap<OpCode, u64>,
+    /// Executions of the instruction starting at each address
+    pub addresses: HashMap<usize, u64>,
+    /// Parameters decoded in each mode
+    pub modes: HashMap<Mode, u64>,
+    pub reads: HashMap<usize, u64>,
+    pub writes: HashMap<usize, u64>,
+    /// Print the report when the program halts
+    report: bool,
+}
+
+impl Profile {
+    fn record(&mut self, pointer: usize, instruction: &Instruction) {
+        self.instructions += 1;
+        *self.opcodes.entry(instruction.opcode).or_insert(0) += 1;
+        *self.addresses.entry(pointer).or_insert(0) += 1;
+        for parameter in instruction.parameters() {
+            *self.modes.entry(parameter.mode).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Most frequent first, ties broken by key
+fn sorted<K: Copy + Ord + Hash>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut counts: Vec<_> = counts.iter().map(|(&key, &count)| (key, count)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |count: u64| 100. * count as f64 / self.instructions.max(1) as f64;
+        writeln!(f, "instructions: {}", self.instructions)?;
+        writeln!(f, "opcodes:")?;
+        for (opcode, count) in sorted(&self.opcodes) {
+            let mnemonic = opcode.mnemonic();
+            writeln!(
+                f,
+                "  {:<4} {:>10} {:>6.2}%",
+                mnemonic,
+                count,
+                percent(count)
+            )?;
+        }
+        writeln!(f, "modes:")?;
+        for (mode, count) in sorted(&self.modes) {
+            writeln!(f, "  {:<9} {:>10}", format!("{:?}", mode), count)?;
+        }
+        let tables = [
+            ("hot instructions", &self.addresses),
+            ("memory reads", &self.reads),
+            ("memory writes", &self.writes),
+        ];
+        for (title, counts) in tables.iter() {
+            writeln!(f, "{}:", title)?;
+            for (address, count) in sorted(counts).into_iter().take(REPORT_ROWS) {
+                writeln!(f, "  {:04} {:>10}", address, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<M: Memory> IntCode<M> {
+    /// Count executed instructions and memory accesses from now on.
+    /// Display the counts from `profile` or `stop_profile` for a report.
+    pub fn start_profile(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    /// `start_profile`, also printing the report when the program halts
+    pub fn start_profile_with_report(&mut self) {
+        self.profile = Some(Profile {
+            report: true,
+            ..Profile::default()
+        });
+    }
+
+    /// Counts so far, if profiling
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Stop profiling and return the counts
+    pub fn stop_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    pub(super) fn profile_instruction(&mut self, instruction: &Instruction) {
+        let pointer = self.pointer;
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(pointer, instruction);
+            if profile.report && instruction.opcode == OpCode::Halt {
+                print!("{}", profile);
+            }
+        }
+    }
+
+    pub(super) fn profile_read(&mut self, address: usize) {
+        if let Some(profile) = self.profile.as_mut() {
+            *profile.reads.entry(address).or_insert(0) += 1;
+        }
+    }
+
+    pub(super) fn profile_write(&mut self, address: usize) {
+        if let Some(profile) = self.profile.as_mut() {
+            *profile.writes.entry(address).or_insert(0) += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::intcode_compiler::countdown;
+    use crate::intcode_compiler::RunState;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = countdown()?;
+        let mut intcode = IntCode::new(program.clone());
+        intcode.push_input(3);
+        intcode.start_profile();
+        assert_eq!(intcode.run()?, RunState::Output(3));
+        assert_eq!(
+            intcode.profile().map(|profile| profile.instructions),
+            Some(2)
+        );
+        intcode.process()?;
+        let profile = intcode.stop_profile().unwrap();
+        assert_eq!(profile.instructions, 11);
+        assert_eq!(profile.opcodes[&OpCode::Output], 3);
+        assert_eq!(profile.opcodes[&OpCode::Halt], 1);
+        assert_eq!(profile.addresses[&4], 3);
+        assert_eq!(profile.modes[&Mode::Position], 13);
+        assert_eq!(profile.modes[&Mode::Immediate], 6);
+        assert_eq!(profile.reads[&12], 9);
+        assert_eq!(profile.writes[&12], 4);
+
+        let report = profile.to_string();
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines[0], "instructions: 11");
+        assert_eq!(lines[2], "  ADD           3  27.27%");
+        assert!(report.contains("memory writes:\n  0012          4\n"));
+
+        let mut intcode = IntCode::new(program);
+        intcode.push_input(3);
+        intcode.start_profile_with_report();
+        intcode.process()?;
+        let profile = intcode.stop_profile().unwrap();
+        assert!(profile.report);
+        assert_eq!(profile.to_string(), report);
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Error;
+
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::{Arithmetic, IntCode};
+use crate::utility::{input_from_file, write_to_file};
+
+const HEADER: &str = "intcode-snapshot 3";
+
+/// Complete machine state, enough to resume execution exactly where it was taken
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshot {
+    /// Non-zero cells as (address, value), in address order
+    pub memory: Vec<(usize, i64)>,
+    /// Length of memory, so trailing zero cells come back too
+    pub size: usize,
+    pub pointer: usize,
+    pub relative_base: i64,
+    pub inputs: Vec<i64>,
+    pub outputs: Vec<i64>,
+    pub halted: bool,
+    pub arithmetic: Arithmetic,
+    pub budget: Option<u64>,
+}
+
+impl<M: Memory + Default> IntCode<M> {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.program.cells(),
+            size: self.program.size(),
+            pointer: self.pointer,
+            relative_base: self.relative_base,
+            inputs: self.inputs.iter().copied().collect(),
+            outputs: self.outputs.clone(),
+            halted: self.halted,
+            arithmetic: self.arithmetic,
+            budget: self.budget,
+        }
+    }
+
+    /// A machine in the snapshot's state, with its memory in whichever backend `M` is
+    pub fn restore(snapshot: Snapshot) -> Self {
+        let mut program = M::default();
+        if snapshot.size > 0 {
+            program.write(snapshot.size - 1, 0);
+        }
+        for (address, value) in snapshot.memory {
+            program.write(address, value);
+        }
+        let mut intcode = IntCode::new(program);
+        intcode.pointer = snapshot.pointer;
+        intcode.relative_base = snapshot.relative_base;
+        intcode.extend_inputs(snapshot.inputs);
+        intcode.outputs = snapshot.outputs;
+        intcode.halted = snapshot.halted;
+        intcode.arithmetic = snapshot.arithmetic;
+        intcode.budget = snapshot.budget;
+        intcode
+    }
+}
+
+impl Snapshot {
+    pub fn save(&self, filename: &str) -> Result<(), Error> {
+        write_to_file(filename, &self.to_string())
+    }
+
+    pub fn load(filename: &str) -> Result<Self, Error> {
+        input_from_file(filename)?.parse()
+    }
+}
+
+fn join(values: &[i64]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// `address:value` pairs
+fn join_cells(cells: &[(usize, i64)]) -> String {
+    cells
+        .iter()
+        .map(|(address, value)| format!("{}:{}", address, value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn split_cells(cells: &str) -> Result<Vec<(usize, i64)>, Error> {
+    if cells.is_empty() {
+        return Ok(Vec::new());
+    }
+    cells
+        .split(',')
+        .map(|cell| {
+            let mut parts = cell.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(address), Some(value)) => Ok((address.parse()?, value.parse()?)),
+                _ => Err(anyhow!("bad memory cell {}", cell)),
+            }
+        })
+        .collect()
+}
+
+fn split(values: &str) -> Result<Vec<i64>, Error> {
+    if values.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(values
+        .split(',')
+        .map(|i| i.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "pointer {}", self.pointer)?;
+        writeln!(f, "relative_base {}", self.relative_base)?;
+        writeln!(f, "halted {}", self.halted)?;
+        writeln!(
+            f,
+            "arithmetic {}",
+            match self.arithmetic {
+                Arithmetic::Wrapping => "wrapping",
+                Arithmetic::Checked => "checked",
+            }
+        )?;
+        match self.budget {
+            Some(budget) => writeln!(f, "budget {}", budget)?,
+            None => writeln!(f, "budget none")?,
+        }
+        writeln!(f, "inputs {}", join(&self.inputs))?;
+        writeln!(f, "outputs {}", join(&self.outputs))?;
+        writeln!(f, "size {}", self.size)?;
+        writeln!(f, "memory {}", join_cells(&self.memory))
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err(anyhow!("not an intcode snapshot"));
+        }
+        let mut field = |name: &str| -> Result<&str, Error> {
+            let line = lines
+                .next()
+                .ok_or_else(|| anyhow!("snapshot missing {}", name))?;
+            let mut parts = line.trim().splitn(2, ' ');
+            match parts.next() {
+                Some(key) if key == name => Ok(parts.next().unwrap_or("")),
+                _ => Err(anyhow!("expected {} in snapshot, found {}", name, line)),
+            }
+        };
+        Ok(Snapshot {
+            pointer: field("pointer")?.parse()?,
+            relative_base: field("relative_base")?.parse()?,
+            halted: field("halted")?.parse()?,
+            arithmetic: match field("arithmetic")? {
+                "wrapping" => Arithmetic::Wrapping,
+                "checked" => Arithmetic::Checked,
+                other => return Err(anyhow!("unknown arithmetic {}", other)),
+            },
+            budget: match field("budget")? {
+                "none" => None,
+                budget => Some(budget.parse()?),
+            },
+            inputs: split(field("inputs")?)?,
+            outputs: split(field("outputs")?)?,
+            size: field("size")?.parse()?,
+            memory: split_cells(field("memory")?)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode_compiler::loader::parse_program;
+    use crate::intcode_compiler::memory::PagedMemory;
+    use crate::intcode_compiler::RunState;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = parse_program("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99")?;
+        let mut intcode = IntCode::new(program.clone());
+        intcode.extend_inputs(vec![-5, 7]);
+        for _ in 0..5 {
+            intcode.run()?;
+        }
+        let snapshot = intcode.snapshot();
+        let text = snapshot.to_string();
+        let restored_snapshot: Snapshot = text.parse()?;
+        assert_eq!(restored_snapshot, snapshot);
+
+        let mut restored: IntCode = IntCode::restore(restored_snapshot);
+        assert_eq!(restored.snapshot(), snapshot);
+        while intcode.run()? != RunState::Halted {}
+        while restored.run()? != RunState::Halted {}
+        assert_eq!(restored.snapshot(), intcode.snapshot());
+        assert_eq!(restored.outputs, program);
+
+        // trailing zero cells are kept
+        let intcode = IntCode::new(vec![1101, 0, 0, 5, 99, 0, 0]);
+        let restored: IntCode = IntCode::restore(intcode.snapshot().to_string().parse()?);
+        assert_eq!(restored.program, intcode.program);
+
+        // a sparse machine with checked arithmetic and a budget
+        let far = 1 << 40;
+        let mut intcode = IntCode::new(PagedMemory::new(&[1101, 5, 6, far, 4, far, 99]));
+        intcode.set_arithmetic(Arithmetic::Checked);
+        intcode.set_budget(Some(10));
+        assert_eq!(intcode.run()?, RunState::Output(11));
+        let snapshot: Snapshot = intcode.snapshot().to_string().parse()?;
+        assert_eq!(snapshot.memory.last(), Some(&(far as usize, 11)));
+        let mut restored: IntCode<PagedMemory> = IntCode::restore(snapshot);
+        assert_eq!(restored.snapshot(), intcode.snapshot());
+        assert_eq!(restored.remaining_budget(), Some(8));
+        assert_eq!(restored.run()?, RunState::Halted);
+
+        assert!("pointer 0".parse::<Snapshot>().is_err());
+        assert!(format!("{}\npointer x", HEADER)
+            .parse::<Snapshot>()
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,504 @@
+//! Symbolic execution: run a program with some memory cells or inputs left unknown,
+//! tracking every value as a polynomial over the unknowns.
+//! Control flow, addresses and comparisons must not depend on the unknowns,
+//! which holds for day 2 style programs that only add and multiply.
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+
+use anyhow::Error;
+
+use crate::intcode_compiler::{
+    execute, Arithmetic, Cell, Core, ErrorContext, Instruction, IntcodeError, Mode, OpCode,
+};
+
+/// Polynomial over the unknowns with integer coefficients.
+/// Each term is keyed by the sorted list of unknowns multiplied together.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Expr {
+    terms: BTreeMap<Vec<usize>, i64>,
+}
+
+impl Expr {
+    pub fn constant(value: i64) -> Self {
+        let mut expr = Expr::default();
+        if value != 0 {
+            expr.terms.insert(Vec::new(), value);
+        }
+        expr
+    }
+
+    pub fn unknown(index: usize) -> Self {
+        let mut expr = Expr::default();
+        expr.terms.insert(vec![index], 1);
+        expr
+    }
+
+    /// The value, if it doesn't depend on any unknown
+    pub fn as_constant(&self) -> Option<i64> {
+        match self.terms.iter().next() {
+            None => Some(0),
+            Some((unknowns, &coefficient)) if unknowns.is_empty() && self.terms.len() == 1 => {
+                Some(coefficient)
+            }
+            _ => None,
+        }
+    }
+
+    fn add_term(&mut self, unknowns: Vec<usize>, coefficient: i64) {
+        let entry = self.terms.entry(unknowns).or_insert(0);
+        *entry = entry.wrapping_add(coefficient);
+        self.terms.retain(|_, coefficient| *coefficient != 0);
+    }
+
+    pub fn add(&self, other: &Expr) -> Expr {
+        let mut sum = self.clone();
+        for (unknowns, &coefficient) in &other.terms {
+            sum.add_term(unknowns.clone(), coefficient);
+        }
+        sum
+    }
+
+    pub fn multiply(&self, other: &Expr) -> Expr {
+        let mut product = Expr::default();
+        for (a, &a_coefficient) in &self.terms {
+            for (b, &b_coefficient) in &other.terms {
+                let mut unknowns: Vec<_> = a.iter().chain(b.iter()).copied().collect();
+                unknowns.sort();
+                product.add_term(unknowns, a_coefficient.wrapping_mul(b_coefficient));
+            }
+        }
+        product
+    }
+
+    /// Value with each unknown `i` set to `values[i]`
+    pub fn evaluate(&self, values: &[i64]) -> i64 {
+        self.terms
+            .iter()
+            .map(|(unknowns, &coefficient)| {
+                unknowns
+                    .iter()
+                    .fold(coefficient, |term, &i| term.wrapping_mul(values[i]))
+            })
+            .fold(0, i64::wrapping_add)
+    }
+
+    fn degree(&self, unknown: usize) -> usize {
+        self.terms
+            .keys()
+            .map(|unknowns| unknowns.iter().filter(|&&i| i == unknown).count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Values for the unknowns, each within its range, that make the expression equal `target`.
+    /// The expression is solved directly for one unknown it is linear in,
+    /// so only the other unknowns' ranges are searched.
+    pub fn solve(&self, target: i64, ranges: &[Range<i64>]) -> Option<Vec<i64>> {
+        if self.terms.keys().flatten().any(|&i| i >= ranges.len()) {
+            return None;
+        }
+        let linear = (0..ranges.len()).rev().find(|&i| self.degree(i) == 1);
+        self.search(target, ranges, linear, 0, &mut vec![0; ranges.len()])
+    }
+
+    fn search(
+        &self,
+        target: i64,
+        ranges: &[Range<i64>],
+        linear: Option<usize>,
+        index: usize,
+        values: &mut Vec<i64>,
+    ) -> Option<Vec<i64>> {
+        if index == ranges.len() {
+            let unknown = match linear {
+                Some(unknown) => unknown,
+                None if self.evaluate(values) == target => return Some(values.clone()),
+                None => return None,
+            };
+            // a * x + b = target
+            values[unknown] = 0;
+            let b = self.evaluate(values);
+            values[unknown] = 1;
+            let a = self.evaluate(values).wrapping_sub(b);
+            let difference = target.wrapping_sub(b);
+            let x = match a {
+                0 if b == target => ranges[unknown].start,
+                0 => return None,
+                // None when dividing i64::MIN by -1 overflows
+                _ => match difference.checked_rem(a) {
+                    Some(0) => difference.checked_div(a)?,
+                    _ => return None,
+                },
+            };
+            values[unknown] = x;
+            return if ranges[unknown].contains(&x) {
+                Some(values.clone())
+            } else {
+                None
+            };
+        }
+        if Some(index) == linear {
+            return self.search(target, ranges, linear, index + 1, values);
+        }
+        for value in ranges[index].clone() {
+            values[index] = value;
+            if let Some(solution) = self.search(target, ranges, linear, index + 1, values) {
+                return Some(solution);
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for Expr {
+    /// e.g. `3*x0*x1 - x1 + 7`, constant last
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        let mut terms: Vec<_> = self.terms.iter().collect();
+        if terms[0].0.is_empty() {
+            terms.rotate_left(1);
+        }
+        for (i, (unknowns, &coefficient)) in terms.into_iter().enumerate() {
+            let sign = if coefficient < 0 { "-" } else { "+" };
+            match i {
+                0 if coefficient < 0 => write!(f, "-")?,
+                0 => (),
+                _ => write!(f, " {} ", sign)?,
+            }
+            let magnitude = coefficient.wrapping_abs();
+            let factors: Vec<_> = unknowns.iter().map(|i| format!("x{}", i)).collect();
+            match (magnitude, factors.is_empty()) {
+                (_, true) => write!(f, "{}", magnitude)?,
+                (1, false) => write!(f, "{}", factors.join("*"))?,
+                _ => write!(f, "{}*{}", magnitude, factors.join("*"))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Contents of a memory cell
+#[derive(Clone, Debug)]
+pub(super) enum Value {
+    Known(Expr),
+    /// Read through an address that depends on the unknowns
+    Opaque,
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Known(a), Value::Known(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Only constants are ordered; `get` makes sure comparisons never see anything else
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.to_i64()?.partial_cmp(&other.to_i64()?)
+    }
+}
+
+impl Cell for Value {
+    fn from_i64(value: i64) -> Self {
+        Value::Known(Expr::constant(value))
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        match self {
+            Value::Known(expr) => expr.as_constant(),
+            Value::Opaque => None,
+        }
+    }
+
+    /// Coefficients wrap, and anything involving an opaque value is opaque
+    fn add(&self, other: &Self, _: Arithmetic) -> Option<Self> {
+        Some(match (self, other) {
+            (Value::Known(a), Value::Known(b)) => Value::Known(a.add(b)),
+            _ => Value::Opaque,
+        })
+    }
+
+    fn multiply(&self, other: &Self, _: Arithmetic) -> Option<Self> {
+        Some(match (self, other) {
+            (Value::Known(a), Value::Known(b)) => Value::Known(a.multiply(b)),
+            _ => Value::Opaque,
+        })
+    }
+}
+
+/// An Intcode machine whose cells and inputs may hold expressions over unknowns
+pub struct SymbolicMachine {
+    memory: Vec<Value>,
+    pointer: usize,
+    relative_base: i64,
+    inputs: VecDeque<Expr>,
+    pub outputs: Vec<Expr>,
+    num_unknowns: usize,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &[i64]) -> Self {
+        SymbolicMachine {
+            memory: program
+                .iter()
+                .map(|&value| Value::from_i64(value))
+                .collect(),
+            pointer: 0,
+            relative_base: 0,
+            inputs: VecDeque::new(),
+            outputs: Vec::new(),
+            num_unknowns: 0,
+        }
+    }
+
+    fn new_unknown(&mut self) -> usize {
+        self.num_unknowns += 1;
+        self.num_unknowns - 1
+    }
+
+    /// Replace the cell at `address` with a new unknown, returning its index
+    pub fn unknown_cell(&mut self, address: usize) -> usize {
+        let unknown = self.new_unknown();
+        self.write(address, Value::Known(Expr::unknown(unknown)));
+        unknown
+    }
+
+    /// Queue a new unknown as input, returning its index
+    pub fn unknown_input(&mut self) -> usize {
+        let unknown = self.new_unknown();
+        self.inputs.push_back(Expr::unknown(unknown));
+        unknown
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(Expr::constant(value));
+    }
+
+    /// None if the cell was read through an address that depends on the unknowns
+    pub fn cell(&self, address: usize) -> Option<Expr> {
+        match self.read(address) {
+            Value::Known(expr) => Some(expr),
+            Value::Opaque => None,
+        }
+    }
+
+    fn read(&self, address: usize) -> Value {
+        self.memory
+            .get(address)
+            .cloned()
+            .unwrap_or_else(|| Value::from_i64(0))
+    }
+
+    fn write(&mut self, address: usize, value: Value) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, Value::from_i64(0));
+        }
+        self.memory[address] = value;
+    }
+
+    fn context(&self) -> ErrorContext {
+        ErrorContext {
+            pointer: self.pointer,
+            instruction: self.read(self.pointer).to_i64().unwrap_or(0),
+            relative_base: self.relative_base,
+        }
+    }
+
+    fn unknown(&self) -> IntcodeError {
+        IntcodeError::DependsOnUnknowns {
+            context: self.context(),
+        }
+    }
+
+    /// Decode the opcode and modes, which must not depend on the unknowns.
+    /// Parameters are read as values when the instruction runs.
+    fn make_instruction(&self) -> Result<Instruction, IntcodeError> {
+        let word = self
+            .read(self.pointer)
+            .to_i64()
+            .ok_or_else(|| self.unknown())?;
+        let pointer = self.pointer;
+        Instruction::decode_with(
+            |address| if address == pointer { word } else { 0 },
+            pointer,
+            self.relative_base,
+        )
+    }
+
+    /// Address parameter `i` of the current instruction points to,
+    /// or None if it depends on the unknowns
+    fn address(&self, mode: Mode, i: usize) -> Result<Option<usize>, IntcodeError> {
+        let value = match self.read(self.pointer + i + 1).to_i64() {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let address = match mode {
+            Mode::Relative => {
+                value
+                    .checked_add(self.relative_base)
+                    .ok_or(IntcodeError::Overflow {
+                        context: self.context(),
+                    })?
+            }
+            _ => value,
+        };
+        if address < 0 {
+            Err(IntcodeError::NegativeAddress {
+                address,
+                context: self.context(),
+            })
+        } else {
+            Ok(Some(address as usize))
+        }
+    }
+
+    /// Run until the program halts
+    pub fn run(&mut self) -> Result<(), Error> {
+        loop {
+            let instruction = self.make_instruction()?;
+            if execute(self, &instruction)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Core for SymbolicMachine {
+    type Cell = Value;
+
+    /// Only sums, products and outputs may be expressions over the unknowns.
+    /// Every other parameter steers the machine, so it has to be a constant.
+    fn get(&mut self, instruction: &Instruction, i: usize) -> Result<Value, IntcodeError> {
+        let value = match instruction.parameters[i].mode {
+            Mode::Immediate => self.read(self.pointer + i + 1),
+            mode => match self.address(mode, i)? {
+                Some(address) => self.read(address),
+                None => Value::Opaque,
+            },
+        };
+        match (instruction.opcode, value) {
+            (OpCode::Add, value) | (OpCode::Multiply, value) => Ok(value),
+            (OpCode::Output, Value::Known(expr)) => Ok(Value::Known(expr)),
+            (_, value) => match value.to_i64() {
+                Some(value) => Ok(Value::from_i64(value)),
+                None => Err(self.unknown()),
+            },
+        }
+    }
+
+    fn set(
+        &mut self,
+        instruction: &Instruction,
+        i: usize,
+        value: Value,
+    ) -> Result<(), IntcodeError> {
+        match instruction.parameters[i].mode {
+            Mode::Immediate => Err(IntcodeError::WriteInImmediateMode {
+                context: self.context(),
+            }),
+            mode => match self.address(mode, i)? {
+                Some(address) => {
+                    self.write(address, value);
+                    Ok(())
+                }
+                None => Err(self.unknown()),
+            },
+        }
+    }
+
+    fn input(&mut self) -> Option<Value> {
+        self.inputs.pop_front().map(Value::Known)
+    }
+
+    fn output(&mut self, value: Value) {
+        if let Value::Known(expr) = value {
+            self.outputs.push(expr);
+        }
+    }
+
+    /// The relative base must not overflow; expressions wrap
+    fn arithmetic(&self) -> Arithmetic {
+        Arithmetic::Checked
+    }
+
+    fn pointer_mut(&mut self) -> &mut usize {
+        &mut self.pointer
+    }
+
+    fn relative_base_mut(&mut self) -> &mut i64 {
+        &mut self.relative_base
+    }
+
+    /// `run` stops when `execute` reports the halt
+    fn halt(&mut self) {}
+
+    fn context(&self) -> ErrorContext {
+        SymbolicMachine::context(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        // day 2 style: the first instruction reads through noun and verb as addresses,
+        // but its result is overwritten before it matters
+        let program = vec![
+            1, 0, 0, 3, 1, 1, 2, 3, 2, 1, 17, 19, 1, 19, 2, 0, 99, 1000, 7, 0,
+        ];
+        let mut machine = SymbolicMachine::new(&program);
+        let noun = machine.unknown_cell(1);
+        let verb = machine.unknown_cell(2);
+        machine.run()?;
+        let output = machine.cell(0).unwrap();
+        assert_eq!(output.to_string(), "1000*x0 + x1");
+        assert!(machine.cell(3).is_some());
+        let solution = output.solve(42_017, &[0..100, 0..100]).unwrap();
+        assert_eq!((solution[noun], solution[verb]), (42, 17));
+        assert_eq!(output.solve(42_017, &[0..100, 0..10]), None);
+        let negated = Expr::unknown(0).multiply(&Expr::constant(-1));
+        assert_eq!(
+            negated.solve(i64::MIN, std::slice::from_ref(&(0..10))),
+            None
+        );
+
+        // square of the input, minus 5
+        let mut machine = SymbolicMachine::new(&[3, 13, 2, 13, 13, 13, 101, -5, 13, 13, 4, 13, 99]);
+        machine.unknown_input();
+        machine.run()?;
+        let output = &machine.outputs[0];
+        assert_eq!(output.to_string(), "x0*x0 - 5");
+        assert_eq!(
+            output.solve(139, std::slice::from_ref(&(0..100))),
+            Some(vec![12])
+        );
+        assert_eq!(output.evaluate(&[3]), 4);
+
+        // branching on an unknown can't be followed
+        let mut machine = SymbolicMachine::new(&[3, 5, 1005, 5, 0, 99]);
+        machine.unknown_input();
+        let error = machine.run().err().unwrap();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(IntcodeError::DependsOnUnknowns { context }) if context.pointer == 2
+        ));
+
+        for program in &[[109, i64::MAX, 109, 1, 99], [109, i64::MAX, 204, 1, 99]] {
+            let error = SymbolicMachine::new(program).run().err().unwrap();
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(IntcodeError::Overflow { context }) if context.pointer == 2
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! IntCode machines running on their own threads, passing values over mpsc channels.
+//! Machines block while waiting for input; if every running machine is waiting
+//! and no value is on its way, the cluster stops and reports a deadlock.
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Error;
+
+use crate::intcode_compiler::device::{InputSource, OutputSink};
+use crate::intcode_compiler::IntCode;
+
+/// How often a waiting machine checks whether the cluster has deadlocked
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Default)]
+struct State {
+    running: usize,
+    waiting: usize,
+    /// Values sent between machines but not yet read
+    in_flight: usize,
+    deadlocked: bool,
+}
+
+impl State {
+    fn check(&mut self) {
+        if self.running > 0 && self.waiting == self.running && self.in_flight == 0 {
+            self.deadlocked = true;
+        }
+    }
+}
+
+type Monitor = Arc<Mutex<State>>;
+
+/// Input side of a machine, fed by every machine connected to it
+struct Inbox {
+    receiver: Receiver<i64>,
+    monitor: Monitor,
+}
+
+impl InputSource for Inbox {
+    /// None once the cluster has deadlocked or every sender has finished
+    fn next_input(&mut self) -> Result<Option<i64>, Error> {
+        match self.receiver.try_recv() {
+            Ok(value) => {
+                self.monitor.lock().unwrap().in_flight -= 1;
+                return Ok(Some(value));
+            }
+            Err(TryRecvError::Disconnected) => return Ok(None),
+            Err(TryRecvError::Empty) => (),
+        }
+        {
+            let mut state = self.monitor.lock().unwrap();
+            state.waiting += 1;
+            state.check();
+        }
+        loop {
+            let received = self.receiver.recv_timeout(POLL_INTERVAL);
+            let mut state = self.monitor.lock().unwrap();
+            match received {
+                Ok(value) => {
+                    state.waiting -= 1;
+                    state.in_flight -= 1;
+                    return Ok(Some(value));
+                }
+                Err(RecvTimeoutError::Timeout) if !state.deadlocked => (),
+                Err(_) => {
+                    state.waiting -= 1;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+/// Output side of a machine, copying each value to every machine it is connected to
+struct Outbox {
+    senders: Vec<Sender<i64>>,
+    monitor: Monitor,
+}
+
+impl OutputSink for Outbox {
+    /// Values for machines that have already finished are dropped
+    fn send(&mut self, value: i64) -> Result<(), Error> {
+        for sender in &self.senders {
+            let mut state = self.monitor.lock().unwrap();
+            if sender.send(value).is_ok() {
+                state.in_flight += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Machines wired output to input, each run on its own thread
+#[derive(Default)]
+pub struct Cluster {
+    machines: Vec<IntCode>,
+    links: Vec<(usize, usize)>,
+}
+
+impl Cluster {
+    pub fn new() -> Self {
+        Cluster::default()
+    }
+
+    /// Add a machine, with any starting input already queued. Returns its index.
+    pub fn add(&mut self, intcode: IntCode) -> usize {
+        self.machines.push(intcode);
+        self.machines.len() - 1
+    }
+
+    /// Feed every output of `from` to `to`
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.links.push((from, to));
+    }
+
+    /// Run every machine until it halts, returning them in the order they were added
+    pub fn run(self) -> Result<Vec<IntCode>, Error> {
+        let Cluster { machines, links } = self;
+        let monitor = Arc::new(Mutex::new(State {
+            running: machines.len(),
+            ..State::default()
+        }));
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..machines.len()).map(|_| channel()).unzip();
+        let handles: Vec<_> = machines
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(index, (mut intcode, receiver))| {
+                let mut inbox = Inbox {
+                    receiver,
+                    monitor: monitor.clone(),
+                };
+                let mut outbox = Outbox {
+                    senders: links
+                        .iter()
+                        .filter(|(from, _)| *from == index)
+                        .map(|(_, to)| senders[*to].clone())
+                        .collect(),
+                    monitor: monitor.clone(),
+                };
+                let monitor = monitor.clone();
+                thread::spawn(move || {
+                    let result = intcode.run_with(&mut inbox, &mut outbox);
+                    let mut state = monitor.lock().unwrap();
+                    // values left unread never arrive, and closing the channel
+                    // stops anything more being counted as on its way here
+                    state.in_flight -= inbox.receiver.try_iter().count();
+                    drop(inbox);
+                    state.running -= 1;
+                    state.check();
+                    result.map(|_| intcode)
+                })
+            })
+            .collect();
+        // only the machines hold senders now,
+        // so a machine with no one left to feed it sees its channel close
+        drop(senders);
+        let mut machines = Vec::new();
+        for (index, handle) in handles.into_iter().enumerate() {
+            let result = handle
+                .join()
+                .map_err(|_| anyhow!("machine {} panicked", index))?;
+            machines.push(result.map_err(|e| anyhow!("machine {}: {}", index, e))?);
+        }
+        let waiting: Vec<_> = (0..machines.len())
+            .filter(|&index| !machines[index].halted)
+            .collect();
+        if monitor.lock().unwrap().deadlocked || !waiting.is_empty() {
+            return Err(anyhow!(
+                "deadlock: machines {:?} are waiting for input",
+                waiting
+            ));
+        }
+        Ok(machines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode_compiler::loader::parse_program;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = parse_program(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        )?;
+        let mut cluster = Cluster::new();
+        for (index, &phase) in [9, 8, 7, 6, 5].iter().enumerate() {
+            let mut amplifier = IntCode::new(program.clone());
+            amplifier.push_input(phase);
+            if index == 0 {
+                amplifier.push_input(0);
+            }
+            cluster.add(amplifier);
+        }
+        for index in 0..5 {
+            cluster.connect(index, (index + 1) % 5);
+        }
+        let machines = cluster.run()?;
+        assert_eq!(machines[4].get_last_output(), Some(139629729));
+        Ok(())
+    }
+
+    #[test]
+    fn test_2() {
+        // each echoes one value from the other, so neither can start
+        let mut cluster = Cluster::new();
+        let a = cluster.add(IntCode::new(vec![3, 0, 4, 0, 99]));
+        let b = cluster.add(IntCode::new(vec![3, 0, 4, 0, 99]));
+        cluster.connect(a, b);
+        cluster.connect(b, a);
+        let error = cluster.run().err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "deadlock: machines [0, 1] are waiting for input"
+        );
+
+        // a reads one of the two values it is sent and halts,
+        // leaving x and y waiting on each other
+        let mut cluster = Cluster::new();
+        let x = cluster.add(IntCode::new(vec![104, 1, 104, 2, 3, 0, 99]));
+        let a = cluster.add(IntCode::new(vec![3, 0, 99]));
+        let y = cluster.add(IntCode::new(vec![3, 0, 3, 0, 3, 0, 99]));
+        cluster.connect(x, a);
+        cluster.connect(x, y);
+        cluster.connect(y, x);
+        let error = cluster.run().err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "deadlock: machines [0, 2] are waiting for input"
+        );
+
+        let mut cluster = Cluster::new();
+        cluster.add(IntCode::new(vec![42]));
+        let error = cluster.run().err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("machine 0: unknown opcode 42"));
+    }
+}
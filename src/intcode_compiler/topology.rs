@@ -0,0 +1,170 @@
+//! Directed graphs of machines, such as the day 7 amplifier chains.
+//! Each machine's outputs are copied to every machine it has an edge to.
+//! Machines take turns on one thread, each running until it needs input,
+//! so a node with several incoming edges always sees its inputs in the same order.
+use anyhow::Error;
+
+use crate::intcode_compiler::{IntCode, RunState};
+
+#[derive(Default)]
+pub struct Topology {
+    nodes: Vec<IntCode>,
+    edges: Vec<(usize, usize)>,
+    result: Option<usize>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Topology::default()
+    }
+
+    /// Amplifiers in a line, each given its phase setting, with `signal` going into the first.
+    /// The result is the output of the last.
+    pub fn series(program: &[i64], phases: &[i64], signal: i64) -> Self {
+        let mut topology = Topology::new();
+        for (i, &phase) in phases.iter().enumerate() {
+            let node = if i == 0 {
+                topology.node(program, &[phase, signal])
+            } else {
+                topology.node(program, &[phase])
+            };
+            if i > 0 {
+                topology.edge(node - 1, node);
+            }
+        }
+        topology
+    }
+
+    /// `series` with the last amplifier's output also fed back into the first
+    pub fn feedback(program: &[i64], phases: &[i64], signal: i64) -> Self {
+        let mut topology = Topology::series(program, phases, signal);
+        if !phases.is_empty() {
+            topology.edge(phases.len() - 1, 0);
+        }
+        topology
+    }
+
+    /// Add a machine running `program` with `inputs` queued, e.g. its phase setting.
+    /// Returns its index.
+    pub fn node(&mut self, program: &[i64], inputs: &[i64]) -> usize {
+        let mut intcode = IntCode::new(program.to_vec());
+        intcode.extend_inputs(inputs.iter().copied());
+        self.nodes.push(intcode);
+        self.nodes.len() - 1
+    }
+
+    pub fn edge(&mut self, from: usize, to: usize) {
+        self.edges.push((from, to));
+    }
+
+    /// Take the result from this node instead of the last one added
+    pub fn set_result(&mut self, node: usize) {
+        self.result = Some(node);
+    }
+
+    /// Run every machine to completion and return the last output of the result node
+    pub fn run(self) -> Result<i64, Error> {
+        let result = match self.result {
+            Some(node) if node < self.nodes.len() => node,
+            Some(node) => return Err(anyhow!("no node {}", node)),
+            None if !self.nodes.is_empty() => self.nodes.len() - 1,
+            None => return Err(anyhow!("empty topology")),
+        };
+        let mut nodes = self.nodes;
+        loop {
+            let mut progress = false;
+            for node in 0..nodes.len() {
+                while !nodes[node].halted {
+                    match nodes[node].run()? {
+                        RunState::Output(value) => {
+                            progress = true;
+                            for &(_, to) in self.edges.iter().filter(|&&(from, _)| from == node) {
+                                nodes[to].push_input(value);
+                            }
+                        }
+                        RunState::NeedsInput | RunState::Halted => break,
+                        state => return Err(anyhow!("node {} stopped with {:?}", node, state)),
+                    }
+                }
+            }
+            // every machine still running has used up its inputs without any new ones being sent
+            if !progress {
+                break;
+            }
+        }
+        let waiting: Vec<_> = (0..nodes.len())
+            .filter(|&node| !nodes[node].halted)
+            .collect();
+        if !waiting.is_empty() {
+            return Err(anyhow!(
+                "deadlock: machines {:?} are waiting for input",
+                waiting
+            ));
+        }
+        nodes[result]
+            .get_last_output()
+            .ok_or_else(|| anyhow!("node {} produced no output", result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        // adds the phase to the signal
+        let program = vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99];
+        let phases: Vec<_> = (1..=12).collect();
+        assert_eq!(Topology::series(&program, &phases, 100).run()?, 178);
+
+        // source -> (doubler, tripler) -> adder
+        let fan = || {
+            let mut topology = Topology::new();
+            let source = topology.node(&[3, 5, 4, 5, 99], &[5]);
+            let double = topology.node(&[3, 9, 1002, 9, 2, 9, 4, 9, 99], &[]);
+            let triple = topology.node(&[3, 9, 1002, 9, 3, 9, 4, 9, 99], &[]);
+            let sum = topology.node(&program, &[]);
+            for &(from, to) in &[
+                (source, double),
+                (source, triple),
+                (double, sum),
+                (triple, sum),
+            ] {
+                topology.edge(from, to);
+            }
+            (topology, double)
+        };
+        assert_eq!(fan().0.run()?, 25);
+        let (mut topology, double) = fan();
+        topology.set_result(double);
+        assert_eq!(topology.run()?, 10);
+
+        // a node that echoes its first input sees the doubled signal, as that edge was added first
+        let mut topology = Topology::new();
+        let source = topology.node(&[3, 5, 4, 5, 99], &[5]);
+        let double = topology.node(&[3, 9, 1002, 9, 2, 9, 4, 9, 99], &[]);
+        let triple = topology.node(&[3, 9, 1002, 9, 3, 9, 4, 9, 99], &[]);
+        let first = topology.node(&[3, 5, 4, 5, 99], &[]);
+        for &(from, to) in &[
+            (source, double),
+            (source, triple),
+            (double, first),
+            (triple, first),
+        ] {
+            topology.edge(from, to);
+        }
+        assert_eq!(topology.run()?, 10);
+
+        // waits forever on a machine that only reads
+        let mut topology = Topology::new();
+        let reader = topology.node(&[3, 0, 3, 0, 99], &[1]);
+        topology.node(&[3, 0, 4, 0, 99], &[]);
+        topology.edge(reader, 1);
+        assert_eq!(
+            topology.run().err().unwrap().to_string(),
+            "deadlock: machines [0, 1] are waiting for input"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+
+use anyhow::Error;
+
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::{Instruction, IntCode, OpCode, MAX_PARAMETERS};
+use crate::utility::write_to_file;
+
+/// Restricts which executed instructions are kept in a trace.
+/// An empty filter keeps everything.
+#[derive(Clone, Debug, Default)]
+pub struct TraceFilter {
+    pub opcodes: Option<HashSet<OpCode>>,
+    /// Keep instructions located in, or writing to, this range of addresses
+    pub addresses: Option<Range<usize>>,
+}
+
+impl TraceFilter {
+    fn matches(&self, record: &TraceRecord) -> bool {
+        let opcode_matches = self
+            .opcodes
+            .iter()
+            .all(|opcodes| opcodes.contains(&record.instruction.opcode));
+        let address_matches = self.addresses.iter().all(|addresses| {
+            addresses.contains(&record.pointer)
+                || record
+                    .write
+                    .iter()
+                    .any(|(address, _)| addresses.contains(address))
+        });
+        opcode_matches && address_matches
+    }
+}
+
+/// One executed instruction, with its parameters resolved against the machine state before execution
+#[derive(Copy, Clone, Debug)]
+pub struct TraceRecord {
+    pub pointer: usize,
+    pub relative_base: i64,
+    pub instruction: Instruction,
+    /// Value of each read parameter (unused past the instruction's read parameters)
+    pub values: [i64; MAX_PARAMETERS],
+    /// Address written to and the value written
+    pub write: Option<(usize, i64)>,
+}
+
+impl TraceRecord {
+    /// None if a parameter doesn't resolve, in which case the instruction itself will fail
+    fn capture<M: Memory>(intcode: &IntCode<M>, instruction: &Instruction) -> Option<Self> {
+        let parameters = instruction.parameters();
+        let reads = if instruction.opcode.writes() {
+            &parameters[..parameters.len() - 1]
+        } else {
+            parameters
+        };
+        let mut values = [0; MAX_PARAMETERS];
+        for (value, parameter) in values.iter_mut().zip(reads) {
+            *value = parameter.peek(intcode).ok()?;
+        }
+        let write = if instruction.opcode.writes() {
+            Some((parameters[parameters.len() - 1].address(intcode).ok()?, 0))
+        } else {
+            None
+        };
+        Some(TraceRecord {
+            pointer: intcode.pointer,
+            relative_base: intcode.relative_base,
+            instruction: *instruction,
+            values,
+            write,
+        })
+    }
+
+    fn num_reads(&self) -> usize {
+        let num_writes = if self.write.is_some() { 1 } else { 0 };
+        self.instruction.parameters().len() - num_writes
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    /// e.g. `0004 rb=0 ADD [12], #3 -> [rb+4] | 5, 3 | [4] = 8`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04} rb={} {}",
+            self.pointer, self.relative_base, self.instruction
+        )?;
+        if self.num_reads() > 0 {
+            let values: Vec<_> = self.values[..self.num_reads()]
+                .iter()
+                .map(|value| value.to_string())
+                .collect();
+            write!(f, " | {}", values.join(", "))?;
+        }
+        if let Some((address, value)) = self.write {
+            write!(f, " | [{}] = {}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Tracer {
+    pub filter: TraceFilter,
+    pub records: Vec<TraceRecord>,
+}
+
+impl Tracer {
+    pub fn new(filter: TraceFilter) -> Self {
+        Tracer {
+            filter,
+            records: Vec::new(),
+        }
+    }
+
+    /// One line per record, so traces of two runs can be diffed
+    pub fn write_to_file(&self, filename: &str) -> Result<(), Error> {
+        let mut contents = self
+            .records
+            .iter()
+            .map(|record| record.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        contents.push('\n');
+        write_to_file(filename, &contents)
+    }
+}
+
+impl<M: Memory> IntCode<M> {
+    /// Record every executed instruction matching `filter` from now on
+    pub fn start_trace(&mut self, filter: TraceFilter) {
+        self.tracer = Some(Tracer::new(filter));
+    }
+
+    /// Stop tracing and return what was recorded
+    pub fn stop_trace(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    pub(super) fn trace_before(&self, instruction: &Instruction) -> Option<TraceRecord> {
+        match self.tracer {
+            Some(_) => TraceRecord::capture(self, instruction),
+            None => None,
+        }
+    }
+
+    pub(super) fn trace_after(&mut self, record: Option<TraceRecord>) {
+        if let Some(mut record) = record {
+            if let Some((address, _)) = record.write {
+                record.write = Some((address, self.program.read(address)));
+            }
+            if let Some(tracer) = self.tracer.as_mut() {
+                if tracer.filter.matches(&record) {
+                    tracer.records.push(record);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode_compiler::countdown;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = countdown()?;
+        let mut intcode = IntCode::new(program.clone());
+        intcode.push_input(2);
+        intcode.start_trace(TraceFilter::default());
+        intcode.process()?;
+        let lines: Vec<_> = intcode
+            .stop_trace()
+            .unwrap()
+            .records
+            .iter()
+            .map(|record| record.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "0000 rb=0 IN -> [12] | [12] = 2",
+                "0002 rb=0 OUT [12] | 2",
+                "0004 rb=0 ADD [12], #-1 -> [12] | 2, -1 | [12] = 1",
+                "0008 rb=0 JT [12], #2 | 1, 2",
+                "0002 rb=0 OUT [12] | 1",
+                "0004 rb=0 ADD [12], #-1 -> [12] | 1, -1 | [12] = 0",
+                "0008 rb=0 JT [12], #2 | 0, 2",
+                "0011 rb=0 HLT",
+            ]
+        );
+
+        let mut intcode = IntCode::new(program);
+        intcode.push_input(2);
+        intcode.start_trace(TraceFilter {
+            opcodes: Some(vec![OpCode::Output, OpCode::Add].into_iter().collect()),
+            addresses: Some(0..4),
+        });
+        intcode.process()?;
+        let pointers: Vec<_> = intcode
+            .stop_trace()
+            .unwrap()
+            .records
+            .iter()
+            .map(|record| record.pointer)
+            .collect();
+        assert_eq!(pointers, vec![2, 2]);
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+//! Ahead-of-time compilation of an Intcode program into Rust source.
+//! The generated `run` is a state machine with one match arm per instruction address,
+//! doing I/O through `InputSource` and `OutputSink`. Each arm first checks that its
+//! instruction is still the one that was compiled. If the program has rewritten its own code,
+//! jumps somewhere that wasn't compiled or hits an error, the machine is handed over
+//! to the interpreter from that instruction on.
+//!
+//! The source is written to be added to this crate as a module, e.g. generate
+//! `transpile_file("data/day_13.input", "src/day_13_native.rs")` and declare it.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use anyhow::Error;
+
+use crate::intcode_compiler::control_flow::ControlFlowGraph;
+use crate::intcode_compiler::device::{InputSource, OutputSink};
+use crate::intcode_compiler::loader::program_from_file;
+use crate::intcode_compiler::{Instruction, IntCode, Mode, OpCode};
+use crate::utility::write_to_file;
+
+/// Cells per line in the generated PROGRAM array
+const ROW: usize = 16;
+
+/// Address a Relative-mode parameter points to,
+/// or None where the interpreter would fail with a negative address or an overflow
+pub fn address(relative_base: i64, offset: i64) -> Option<usize> {
+    offset
+        .checked_add(relative_base)
+        .filter(|&address| address >= 0)
+        .map(|address| address as usize)
+}
+
+/// The interpreter's machine, stopped before the instruction at `pointer`.
+/// Its `outputs` only record what it outputs from now on.
+pub fn suspend(memory: Vec<i64>, pointer: usize, relative_base: i64) -> IntCode {
+    let mut intcode = IntCode::new(memory);
+    intcode.pointer = pointer;
+    intcode.relative_base = relative_base;
+    intcode
+}
+
+/// Carry on from `pointer` on the interpreter, stopping as `IntCode::run_with` does
+pub fn resume<I: InputSource, O: OutputSink>(
+    memory: Vec<i64>,
+    pointer: usize,
+    relative_base: i64,
+    input: &mut I,
+    output: &mut O,
+) -> Result<IntCode, Error> {
+    let mut intcode = suspend(memory, pointer, relative_base);
+    intcode.run_with(input, output)?;
+    Ok(intcode)
+}
+
+/// Instructions to compile: the code reachable from address 0, and from every address
+/// used as an Immediate value, since that is how programs push return addresses
+fn instructions(program: &[i64]) -> BTreeMap<usize, Instruction> {
+    let mut entries = BTreeSet::new();
+    entries.insert(0);
+    loop {
+        let roots: Vec<_> = entries.iter().copied().collect();
+        let instructions: BTreeMap<_, _> = ControlFlowGraph::build_from(program, &roots)
+            .blocks
+            .values()
+            .flat_map(|block| block.instructions.iter().copied())
+            .collect();
+        let found: Vec<_> = instructions
+            .values()
+            .flat_map(|instruction| instruction.parameters().iter())
+            .filter(|parameter| parameter.mode == Mode::Immediate)
+            .filter(|parameter| parameter.value >= 0 && (parameter.value as usize) < program.len())
+            .map(|parameter| parameter.value as usize)
+            .filter(|address| !entries.contains(address))
+            .collect();
+        if found.is_empty() {
+            return instructions;
+        }
+        entries.extend(found);
+    }
+}
+
+/// Lines of one match arm, or None if the instruction always fails
+/// and is better left to the interpreter
+fn arm(pointer: usize, instruction: &Instruction, program: &[i64]) -> Option<Vec<String>> {
+    let parameters = instruction.parameters();
+    let next = pointer + parameters.len() + 1;
+    let mut lines = vec![
+        format!(
+            "if memory[{}..{}] != {:?} {{",
+            pointer,
+            next,
+            &program[pointer..next]
+        ),
+        "    break;".to_owned(),
+        "}".to_owned(),
+    ];
+    let mut addresses = Vec::new();
+    for (i, parameter) in parameters.iter().enumerate() {
+        addresses.push(match parameter.mode {
+            Mode::Immediate => None,
+            Mode::Position if parameter.value < 0 => return None,
+            Mode::Position => Some(parameter.value.to_string()),
+            Mode::Relative => {
+                lines.push(format!(
+                    "let a{} = match address(relative_base, {}) {{",
+                    i, parameter.value
+                ));
+                lines.push("    Some(address) => address,".to_owned());
+                lines.push("    None => break,".to_owned());
+                lines.push("};".to_owned());
+                Some(format!("a{}", i))
+            }
+        });
+    }
+    let read = |i: usize| match &addresses[i] {
+        Some(address) => format!("memory.read({})", address),
+        None => format!("{}_i64", parameters[i].value),
+    };
+    // a method call binds tighter than a minus sign
+    let receiver = |i: usize| match parameters[i].value {
+        value if value < 0 && addresses[i].is_none() => format!("({}_i64)", value),
+        _ => read(i),
+    };
+    let write = |i: usize, lines: &mut Vec<String>| {
+        addresses[i]
+            .as_ref()
+            .map(|address| lines.push(format!("memory.write({}, value);", address)))
+    };
+    let jump = |condition: &str| {
+        format!(
+            "pointer = if {} {} 0 {{ {} as usize }} else {{ {} }};",
+            read(0),
+            condition,
+            read(1),
+            next
+        )
+    };
+    match instruction.opcode {
+        OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => {
+            lines.push(match instruction.opcode {
+                OpCode::Add => format!("let value = {}.wrapping_add({});", receiver(0), read(1)),
+                OpCode::Multiply => {
+                    format!("let value = {}.wrapping_mul({});", receiver(0), read(1))
+                }
+                OpCode::LessThan => format!("let value = ({} < {}) as i64;", read(0), read(1)),
+                _ => format!("let value = ({} == {}) as i64;", read(0), read(1)),
+            });
+            write(2, &mut lines)?;
+        }
+        OpCode::Input => {
+            lines.push("let value = match input.next_input()? {".to_owned());
+            lines.push("    Some(value) => value,".to_owned());
+            lines.push(format!(
+                "    None => return Ok(suspend(memory, {}, relative_base)),",
+                pointer
+            ));
+            lines.push("};".to_owned());
+            write(0, &mut lines)?;
+        }
+        OpCode::Output => lines.push(format!("output.send({})?;", read(0))),
+        OpCode::JumpIfTrue => {
+            lines.push(jump("!="));
+            return Some(lines);
+        }
+        OpCode::JumpIfFalse => {
+            lines.push(jump("=="));
+            return Some(lines);
+        }
+        OpCode::RelativeBaseOffset => lines.push(format!(
+            "relative_base = relative_base.wrapping_add({});",
+            read(0)
+        )),
+        // the interpreter sets the machine halted
+        OpCode::Halt => return None,
+    }
+    lines.push(format!("pointer = {};", next));
+    Some(lines)
+}
+
+/// Rust source for a module whose `run` executes `program`,
+/// returning the machine once it halts or `input` has nothing left to give
+pub fn transpile(program: &[i64]) -> String {
+    let mut source = String::new();
+    let mut line = |text: &str| writeln!(source, "{}", text).unwrap();
+    line("//! Generated by intcode_compiler::transpiler, do not edit");
+    line("#![allow(clippy::all, unused_mut)]");
+    line("use anyhow::Error;");
+    line("");
+    line("use crate::intcode_compiler::device::{InputSource, OutputSink};");
+    line("use crate::intcode_compiler::memory::Memory;");
+    line("use crate::intcode_compiler::transpiler::{address, resume, suspend};");
+    line("use crate::intcode_compiler::IntCode;");
+    line("");
+    line(&format!("const PROGRAM: [i64; {}] = [", program.len()));
+    for row in program.chunks(ROW) {
+        let cells: Vec<_> = row.iter().map(|cell| cell.to_string()).collect();
+        line(&format!("    {},", cells.join(", ")));
+    }
+    line("];");
+    line("");
+    line("pub fn run<I: InputSource, O: OutputSink>(");
+    line("    input: &mut I,");
+    line("    output: &mut O,");
+    line(") -> Result<IntCode, Error> {");
+    line("    let mut memory = PROGRAM.to_vec();");
+    line("    let mut pointer = 0;");
+    line("    let mut relative_base: i64 = 0;");
+    line("    loop {");
+    line("        match pointer {");
+    for (pointer, instruction) in instructions(program) {
+        if let Some(lines) = arm(pointer, &instruction, program) {
+            line(&format!("            // {}", instruction));
+            line(&format!("            {} => {{", pointer));
+            for text in lines {
+                line(&format!("                {}", text));
+            }
+            line("            }");
+        }
+    }
+    line("            _ => break,");
+    line("        }");
+    line("    }");
+    line("    resume(memory, pointer, relative_base, input, output)");
+    line("}");
+    source
+}
+
+/// Compile the program in `input_file` into the Rust module `output_file`
+pub fn transpile_file(input_file: &str, output_file: &str) -> Result<(), Error> {
+    write_to_file(output_file, &transpile(&program_from_file(input_file)?))
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod example;
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode_compiler::assembler::assemble;
+    use crate::intcode_compiler::RunState;
+
+    use super::*;
+
+    /// Source of the program in `transpiler/example.rs`
+    const EXAMPLE: &str = "
+                    ARB #stack
+                    IN -> [n]
+            loop:   ADD #back, #0 -> [rb+0]
+                    JT #1, #square
+            back:   OUT [rb+1]
+                    ADD [n], #-1 -> [n]
+                    JT [n], #loop
+                    ADD #4, #0 -> [patch]
+            patch:  OUT #5
+                    HLT
+            square: MUL [n], [n] -> [rb+1]
+                    JT #1, [rb+0]
+            n:      .data 0
+            stack:  .data 0";
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        let program = assemble(EXAMPLE)?;
+        assert_eq!(transpile(&program), include_str!("transpiler/example.rs"));
+
+        let mut inputs = vec![3].into_iter();
+        let mut interpreted = IntCode::new(program);
+        interpreted.run_with(&mut || inputs.next(), &mut Vec::new())?;
+        assert_eq!(interpreted.outputs, vec![9, 4, 1, 11]);
+
+        // the program rewrites the instruction at `patch`, so that one runs interpreted
+        let mut inputs = vec![3].into_iter();
+        let mut outputs = Vec::new();
+        let native = example::run(&mut || inputs.next(), &mut outputs)?;
+        assert_eq!(outputs, interpreted.outputs);
+        assert!(native.halted);
+        assert_eq!(native.program, interpreted.program);
+
+        // stops for input, and can be carried on by the interpreter
+        let mut native = example::run(&mut || None, &mut outputs)?;
+        assert!(!native.halted);
+        native.push_input(2);
+        assert_eq!(native.run()?, RunState::Output(4));
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Generated by intcode_compiler::transpiler, do not edit
+#![allow(clippy::all, unused_mut)]
+use anyhow::Error;
+
+use crate::intcode_compiler::device::{InputSource, OutputSink};
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::transpiler::{address, resume, suspend};
+use crate::intcode_compiler::IntCode;
+
+const PROGRAM: [i64; 36] = [
+    109, 35, 3, 34, 21101, 11, 0, 0, 1105, 1, 27, 204, 1, 1001, 34, -1,
+    34, 1005, 34, 4, 1101, 4, 0, 24, 104, 5, 99, 20002, 34, 34, 1, 2105,
+    1, 0, 0, 0,
+];
+
+pub fn run<I: InputSource, O: OutputSink>(
+    input: &mut I,
+    output: &mut O,
+) -> Result<IntCode, Error> {
+    let mut memory = PROGRAM.to_vec();
+    let mut pointer = 0;
+    let mut relative_base: i64 = 0;
+    loop {
+        match pointer {
+            // ARB #35
+            0 => {
+                if memory[0..2] != [109, 35] {
+                    break;
+                }
+                relative_base = relative_base.wrapping_add(35_i64);
+                pointer = 2;
+            }
+            // IN -> [34]
+            2 => {
+                if memory[2..4] != [3, 34] {
+                    break;
+                }
+                let value = match input.next_input()? {
+                    Some(value) => value,
+                    None => return Ok(suspend(memory, 2, relative_base)),
+                };
+                memory.write(34, value);
+                pointer = 4;
+            }
+            // ADD #11, #0 -> [rb+0]
+            4 => {
+                if memory[4..8] != [21101, 11, 0, 0] {
+                    break;
+                }
+                let a2 = match address(relative_base, 0) {
+                    Some(address) => address,
+                    None => break,
+                };
+                let value = 11_i64.wrapping_add(0_i64);
+                memory.write(a2, value);
+                pointer = 8;
+            }
+            // JT #1, #27
+            8 => {
+                if memory[8..11] != [1105, 1, 27] {
+                    break;
+                }
+                pointer = if 1_i64 != 0 { 27_i64 as usize } else { 11 };
+            }
+            // OUT [rb+1]
+            11 => {
+                if memory[11..13] != [204, 1] {
+                    break;
+                }
+                let a0 = match address(relative_base, 1) {
+                    Some(address) => address,
+                    None => break,
+                };
+                output.send(memory.read(a0))?;
+                pointer = 13;
+            }
+            // ADD [34], #-1 -> [34]
+            13 => {
+                if memory[13..17] != [1001, 34, -1, 34] {
+                    break;
+                }
+                let value = memory.read(34).wrapping_add(-1_i64);
+                memory.write(34, value);
+                pointer = 17;
+            }
+            // JT [34], #4
+            17 => {
+                if memory[17..20] != [1005, 34, 4] {
+                    break;
+                }
+                pointer = if memory.read(34) != 0 { 4_i64 as usize } else { 20 };
+            }
+            // ADD #4, #0 -> [24]
+            20 => {
+                if memory[20..24] != [1101, 4, 0, 24] {
+                    break;
+                }
+                let value = 4_i64.wrapping_add(0_i64);
+                memory.write(24, value);
+                pointer = 24;
+            }
+            // OUT #5
+            24 => {
+                if memory[24..26] != [104, 5] {
+                    break;
+                }
+                output.send(5_i64)?;
+                pointer = 26;
+            }
+            // MUL [34], [34] -> [rb+1]
+            27 => {
+                if memory[27..31] != [20002, 34, 34, 1] {
+                    break;
+                }
+                let a2 = match address(relative_base, 1) {
+                    Some(address) => address,
+                    None => break,
+                };
+                let value = memory.read(34).wrapping_mul(memory.read(34));
+                memory.write(a2, value);
+                pointer = 31;
+            }
+            // JT #1, [rb+0]
+            31 => {
+                if memory[31..34] != [2105, 1, 0] {
+                    break;
+                }
+                let a1 = match address(relative_base, 0) {
+                    Some(address) => address,
+                    None => break,
+                };
+                pointer = if 1_i64 != 0 { memory.read(a1) as usize } else { 34 };
+            }
+            _ => break,
+        }
+    }
+    resume(memory, pointer, relative_base, input, output)
+}
@@ -0,0 +1,182 @@
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use crate::intcode_compiler::memory::Memory;
+use crate::intcode_compiler::IntCode;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WatchKind {
+    Read,
+    /// Any write, even one that stores the value already there
+    Write,
+    /// A write that changes the stored value
+    Change,
+}
+
+/// A watched memory access. For reads `old` and `new` are both the value read.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub kind: WatchKind,
+    pub pointer: usize,
+    pub address: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+type Callback = Arc<Mutex<dyn FnMut(&WatchHit) + Send>>;
+
+#[derive(Clone)]
+struct Watchpoint {
+    id: usize,
+    addresses: Range<usize>,
+    kind: WatchKind,
+    /// None pauses the machine instead
+    callback: Option<Callback>,
+}
+
+impl Watchpoint {
+    fn fires(&self, kind: WatchKind, address: usize, old: i64, new: i64) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Read => kind == WatchKind::Read,
+            WatchKind::Write => kind == WatchKind::Write,
+            WatchKind::Change => kind == WatchKind::Write && old != new,
+        };
+        kind_matches && self.addresses.contains(&address)
+    }
+}
+
+#[derive(Clone, Default)]
+pub(super) struct Watches {
+    points: Vec<Watchpoint>,
+    next_id: usize,
+    /// First pausing hit of the current instruction, reported by the next `run`
+    pub(super) hit: Option<WatchHit>,
+}
+
+impl Watches {
+    #[inline]
+    pub(super) fn is_active(&self) -> bool {
+        !self.points.is_empty()
+    }
+}
+
+impl<M: Memory> IntCode<M> {
+    fn add_watchpoint(
+        &mut self,
+        addresses: Range<usize>,
+        kind: WatchKind,
+        callback: Option<Callback>,
+    ) -> usize {
+        let id = self.watches.next_id;
+        self.watches.next_id += 1;
+        self.watches.points.push(Watchpoint {
+            id,
+            addresses,
+            kind,
+            callback,
+        });
+        id
+    }
+
+    /// Make `run` return RunState::Watchpoint after an instruction accesses these addresses.
+    /// Returns an id for `unwatch`.
+    pub fn watch(&mut self, addresses: Range<usize>, kind: WatchKind) -> usize {
+        self.add_watchpoint(addresses, kind, None)
+    }
+
+    /// Call `callback` whenever an instruction accesses these addresses, without pausing
+    pub fn watch_with<F: FnMut(&WatchHit) + Send + 'static>(
+        &mut self,
+        addresses: Range<usize>,
+        kind: WatchKind,
+        callback: F,
+    ) -> usize {
+        self.add_watchpoint(addresses, kind, Some(Arc::new(Mutex::new(callback))))
+    }
+
+    pub fn unwatch(&mut self, id: usize) {
+        self.watches.points.retain(|watchpoint| watchpoint.id != id);
+    }
+
+    /// Called by `Parameter::get` and `Parameter::set` for every memory access while watchpoints are set
+    pub(super) fn notify_watches(&mut self, kind: WatchKind, address: usize, old: i64, new: i64) {
+        for watchpoint in &self.watches.points {
+            if !watchpoint.fires(kind, address, old, new) {
+                continue;
+            }
+            let hit = WatchHit {
+                id: watchpoint.id,
+                kind: watchpoint.kind,
+                pointer: self.pointer,
+                address,
+                old,
+                new,
+            };
+            match &watchpoint.callback {
+                Some(callback) => (callback.lock().unwrap())(&hit),
+                None => {
+                    if self.watches.hit.is_none() {
+                        self.watches.hit = Some(hit)
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::intcode_compiler::assembler::assemble;
+    use crate::intcode_compiler::RunState;
+
+    use super::*;
+
+    #[test]
+    fn test_1() -> Result<(), Error> {
+        // keeps a running total of its inputs in a relative-mode cell
+        let program = assemble(
+            "       ARB #100
+            loop:   IN -> [rb+0]
+                    ADD [rb+0], [score] -> [score]
+                    OUT [score]
+                    JT #1, #loop
+            score:  .data 0",
+        )?;
+        let mut intcode = IntCode::new(program);
+        let scores = Arc::new(Mutex::new(Vec::new()));
+        let seen = scores.clone();
+        intcode.watch_with(13..14, WatchKind::Change, move |hit| {
+            seen.lock().unwrap().push(hit.new)
+        });
+        let write = intcode.watch(100..101, WatchKind::Write);
+        intcode.extend_inputs(vec![3, 0, 4]);
+
+        let hit = WatchHit {
+            id: write,
+            kind: WatchKind::Write,
+            pointer: 2,
+            address: 100,
+            old: 0,
+            new: 3,
+        };
+        assert_eq!(intcode.run()?, RunState::Watchpoint(hit));
+        assert_eq!(intcode.run()?, RunState::Output(3));
+        intcode.unwatch(write);
+        let read = intcode.watch(13..14, WatchKind::Read);
+        match intcode.run()? {
+            RunState::Watchpoint(hit) => {
+                assert_eq!((hit.id, hit.pointer, hit.new), (read, 4, 3));
+            }
+            state => panic!("expected a watchpoint, found {:?}", state),
+        }
+        intcode.unwatch(read);
+        assert_eq!(intcode.run()?, RunState::Output(3));
+        assert_eq!(intcode.run()?, RunState::Output(7));
+        assert_eq!(intcode.run()?, RunState::NeedsInput);
+        assert_eq!(*scores.lock().unwrap(), vec![3, 7]);
+        Ok(())
+    }
+}